@@ -0,0 +1,45 @@
+// Serial vs rayon enrichment over synthetic batches of growing size.
+// Run with `cargo bench --features parallel` from the crate root so the OUI CSV resolves.
+use std::time::{Duration, Instant};
+use wifi_module::{convert_to_wifi_data, convert_to_wifi_data_parallel, Config, OuiDatabase};
+
+const BATCH_SIZES: [usize; 7] = [10, 100, 500, 1_000, 5_000, 10_000, 100_000];
+const ROUNDS: u32 = 20;
+
+fn synthetic_networks(count: usize) -> Vec<tokio_wifiscanner::Wifi> {
+    (0..count)
+        .map(|i| tokio_wifiscanner::Wifi {
+            mac: format!("10:E9:92:{:02x}:{:02x}:{:02x}", (i >> 16) & 0xff, (i >> 8) & 0xff, i & 0xff),
+            ssid: format!("Network {}", i),
+            channel: ((i % 11) + 1).to_string(),
+            signal_level: format!("-{}", 40 + i % 50),
+            security: "WPA2(PSK/AES/AES)".to_string(),
+        })
+        .collect()
+}
+
+fn average<F: FnMut()>(mut run: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        run();
+    }
+    start.elapsed() / ROUNDS
+}
+
+fn main() {
+    let oui = OuiDatabase::load_from_csv("src/database/oui.csv").expect("OUI database");
+    let config: Config = serde_json::from_str("{}").unwrap();
+
+    println!("{:>8} {:>12} {:>12}", "records", "serial", "parallel");
+    for size in BATCH_SIZES {
+        let networks = synthetic_networks(size);
+        let serial = average(|| {
+            convert_to_wifi_data(&networks, &oui, &config);
+        });
+        let parallel = average(|| {
+            convert_to_wifi_data_parallel(&networks, &oui, &config);
+        });
+        let marker = if parallel < serial { "  <- parallel faster" } else { "" };
+        println!("{:>8} {:>12?} {:>12?}{}", size, serial, parallel, marker);
+    }
+}
@@ -0,0 +1,70 @@
+// ManufacturerTrie vs the flat HashMap it replaced, loading the full bundled registry and resolving MACs.
+// Run with `cargo bench --bench oui` from the crate root so the OUI CSV resolves.
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use wifi_module::ManufacturerTrie;
+
+const REGISTRY: &str = "src/database/oui.csv";
+const LOOKUPS: usize = 100_000;
+const ROUNDS: u32 = 5;
+
+// (assignment hex, organization name) rows of the registry
+fn registry_rows() -> Vec<(String, String)> {
+    let mut rdr = csv::Reader::from_path(REGISTRY).expect("OUI database");
+    rdr.records()
+        .map(|record| record.expect("OUI row"))
+        .map(|record| (record[1].to_uppercase(), record[2].to_string()))
+        .collect()
+}
+
+// The old scheme: one map keyed by the 6, 7 or 9 digit prefix, probed from most to least specific
+fn hashmap_lookup<'a>(map: &'a HashMap<String, String>, mac_hex: &str) -> Option<&'a str> {
+    [9, 7, 6].iter()
+        .filter_map(|&nibbles| mac_hex.get(..nibbles))
+        .find_map(|prefix| map.get(prefix))
+        .map(String::as_str)
+}
+
+fn average<F: FnMut()>(mut run: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        run();
+    }
+    start.elapsed() / ROUNDS
+}
+
+fn main() {
+    let rows = registry_rows();
+    // Half registered prefixes, half random ones that mostly miss
+    let macs: Vec<String> = (0..LOOKUPS)
+        .map(|i| match i % 2 {
+            0 => format!("{}{:06X}", rows[i % rows.len()].0, i & 0xffffff),
+            _ => format!("{:012X}", (i as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) & 0xffff_ffff_ffff),
+        })
+        .collect();
+
+    let mut map = HashMap::new();
+    let map_load = average(|| {
+        map = rows.iter().cloned().collect::<HashMap<String, String>>();
+    });
+    let mut trie = ManufacturerTrie::new();
+    let trie_load = average(|| {
+        trie = ManufacturerTrie::new();
+        for (prefix, name) in &rows {
+            trie.insert(prefix, name);
+        }
+    });
+
+    let mut hits = 0;
+    let map_lookup = average(|| {
+        hits = macs.iter().filter(|mac| hashmap_lookup(&map, mac).is_some()).count();
+    });
+    let trie_lookup = average(|| {
+        assert_eq!(macs.iter().filter(|mac| trie.lookup(mac).is_some()).count(), hits);
+    });
+
+    println!("{} prefixes, {} lookups ({} hits)", rows.len(), LOOKUPS, hits);
+    println!("{:>8} {:>12} {:>12}", "", "load", "lookups");
+    println!("{:>8} {:>12?} {:>12?}", "hashmap", map_load, map_lookup);
+    println!("{:>8} {:>12?} {:>12?}", "trie", trie_load, trie_lookup);
+}
@@ -0,0 +1,44 @@
+use crate::{Location, ScanError};
+use chrono::{DateTime, Utc};
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+// gpsd streams a report about once a second, so a few seconds is plenty to see a fix
+const FIX_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Asks the gpsd at `addr` (e.g. "127.0.0.1:2947") for the next 2D or 3D fix from its JSON protocol
+pub async fn current_fix(addr: &str) -> Result<Location, ScanError> {
+    tokio::time::timeout(FIX_TIMEOUT, read_fix(addr)).await.unwrap_or_else(|_| {
+        Err(ScanError::Gps(io::Error::new(io::ErrorKind::TimedOut, "no fix reported by gpsd")))
+    })
+}
+
+async fn read_fix(addr: &str) -> Result<Location, ScanError> {
+    let mut stream = TcpStream::connect(addr).await.map_err(ScanError::Gps)?;
+    stream.write_all(b"?WATCH={\"enable\":true,\"json\":true};\n").await.map_err(ScanError::Gps)?;
+    let mut lines = BufReader::new(stream).lines();
+    while let Some(line) = lines.next_line().await.map_err(ScanError::Gps)? {
+        if let Some(location) = parse_tpv(&line) {
+            return Ok(location);
+        }
+    }
+    Err(ScanError::Gps(io::Error::new(io::ErrorKind::UnexpectedEof, "gpsd closed the connection")))
+}
+
+// Position reports are "TPV" objects; mode 2 is a 2D fix and 3 adds altitude
+fn parse_tpv(line: &str) -> Option<Location> {
+    let report: serde_json::Value = serde_json::from_str(line).ok()?;
+    if report["class"] != "TPV" || report["mode"].as_u64().unwrap_or(0) < 2 {
+        return None;
+    }
+    Some(Location {
+        latitude: report["lat"].as_f64()?,
+        longitude: report["lon"].as_f64()?,
+        altitude: report["altMSL"].as_f64().or_else(|| report["alt"].as_f64()),
+        fix_time: report["time"].as_str()
+            .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+            .map(|time| time.with_timezone(&Utc)),
+    })
+}
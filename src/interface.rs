@@ -0,0 +1,251 @@
+use crate::ScanError;
+use tokio::process::Command;
+use tokio::task::JoinSet;
+use tracing::warn;
+
+// Wireless interfaces the platform knows about, e.g. ["wlan0", "wlan1"]
+pub async fn list_interfaces() -> Result<Vec<String>, ScanError> {
+    #[cfg(target_os = "linux")]
+    let names = labelled_values(&run("iw", &["dev"]).await?, "Interface ");
+    #[cfg(target_os = "windows")]
+    let names = labelled_values(&run("netsh", &["wlan", "show", "interfaces"]).await?, "Name");
+    #[cfg(target_os = "macos")]
+    let names = parse_hardware_ports(&run("networksetup", &["-listallhardwareports"]).await?);
+    #[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+    let names = Vec::new();
+
+    Ok(names)
+}
+
+// The value of every line starting with `label`
+#[cfg_attr(not(any(target_os = "linux", target_os = "windows")), allow(dead_code))]
+fn labelled_values(output: &str, label: &str) -> Vec<String> {
+    output.lines()
+        .filter_map(|line| line.trim().strip_prefix(label))
+        // netsh pads its labels: "Name                   : Wi-Fi"
+        .map(|rest| rest.trim_start_matches([' ', ':']).trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+// `networksetup -listallhardwareports` lists Ethernet, Thunderbolt and Bluetooth ports as well, so only
+// the "Device:" of a "Hardware Port: Wi-Fi" block (AirPort before 10.7) is kept
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn parse_hardware_ports(output: &str) -> Vec<String> {
+    let mut wireless = false;
+    let mut names = Vec::new();
+    for line in output.lines().map(str::trim) {
+        if let Some(port) = line.strip_prefix("Hardware Port:") {
+            wireless = matches!(port.trim(), "Wi-Fi" | "AirPort");
+        } else if let Some(device) = line.strip_prefix("Device:").map(str::trim) {
+            if wireless && !device.is_empty() {
+                names.push(device.to_string());
+            }
+        }
+    }
+    names
+}
+
+// Fails with the list of known interfaces when `name` isn't one of them
+pub async fn ensure_exists(name: &str) -> Result<(), ScanError> {
+    let interfaces = list_interfaces().await?;
+    if interfaces.iter().any(|interface| interface == name) {
+        return Ok(());
+    }
+    Err(ScanError::Interface(format!("no wireless interface named \"{}\" (available: {})", name, interfaces.join(", "))))
+}
+
+// tokio_wifiscanner always scans the first interface, so a named one is scanned with `iw` directly.
+// With `freq_mhz` the adapter only dwells on that channel, which passive-only drivers need to see the rest.
+#[cfg(target_os = "linux")]
+pub(crate) async fn scan_interface(name: &str, freq_mhz: Option<u32>) -> Result<Vec<tokio_wifiscanner::Wifi>, ScanError> {
+    let freq = freq_mhz.map(|mhz| mhz.to_string());
+    let mut args = vec!["dev", name, "scan"];
+    if let Some(freq) = &freq {
+        args.extend(["freq", freq.as_str()]);
+    }
+    Ok(parse_iw_scan(&run("iw", &args).await?))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) async fn scan_interface(_name: &str, _freq_mhz: Option<u32>) -> Result<Vec<tokio_wifiscanner::Wifi>, ScanError> {
+    Err(ScanError::Interface("choosing an interface is only supported on Linux".to_string()))
+}
+
+// Scans every interface at once. Each network comes back with the interface that saw it; an interface that
+// fails is logged and skipped unless all of them fail.
+pub(crate) async fn scan_interfaces(names: &[String], freq_mhz: Option<u32>) -> Result<Vec<(String, tokio_wifiscanner::Wifi)>, ScanError> {
+    let mut scans = JoinSet::new();
+    for name in names {
+        let name = name.clone();
+        scans.spawn(async move {
+            let result = scan_interface(&name, freq_mhz).await;
+            (name, result)
+        });
+    }
+    let mut networks = Vec::new();
+    let mut first_error = None;
+    let mut succeeded = false;
+    while let Some(joined) = scans.join_next().await {
+        let (name, result) = joined.map_err(|e| ScanError::Interface(format!("scan task failed: {}", e)))?;
+        match result {
+            Ok(found) => {
+                succeeded = true;
+                networks.extend(found.into_iter().map(|network| (name.clone(), network)));
+            }
+            Err(e) => {
+                warn!("Scan of {} failed: {}", name, e);
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    match first_error {
+        Some(e) if !succeeded => Err(e),
+        _ => Ok(networks),
+    }
+}
+
+async fn run(program: &str, args: &[&str]) -> Result<String, ScanError> {
+    let output = Command::new(program)
+        .args(args)
+        // iw usually lives in /usr/sbin, which isn't on every user's PATH
+        .env("PATH", format!("{}:/usr/sbin:/sbin", std::env::var("PATH").unwrap_or_default()))
+        .output()
+        .await
+        .map_err(|e| ScanError::Interface(format!("could not run {}: {}", program, e)))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(ScanError::Interface(format!("{} failed: {}", program, stderr.trim())));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// One record per "BSS" block of `iw dev <name> scan`. Unlike the tokio_wifiscanner parser this keeps
+// hidden networks, puts the frequency in `channel` when there's no DS channel (5/6 GHz) and fills in
+// `security` as e.g. "RSN(PSK SAE)", "WPA(PSK)" or "WEP" so `Security::parse` can classify it.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_iw_scan(output: &str) -> Vec<tokio_wifiscanner::Wifi> {
+    let mut networks = Vec::new();
+    let mut current: Option<IwBss> = None;
+    let mut section = "";
+    for line in output.lines() {
+        if let Some(rest) = line.strip_prefix("BSS ") {
+            networks.extend(current.take().map(IwBss::finish));
+            let mac = rest.split(['(', ' ']).next().unwrap_or_default();
+            current = Some(IwBss { mac: mac.to_string(), ..IwBss::default() });
+            continue;
+        }
+        let Some(bss) = current.as_mut() else {
+            continue;
+        };
+        let trimmed = line.trim();
+        if let Some(ssid) = line.strip_prefix("\tSSID:") {
+            bss.ssid = ssid.trim().to_string();
+        } else if let Some(signal) = trimmed.strip_prefix("signal:") {
+            bss.signal = signal.trim().trim_end_matches("dBm").trim().to_string();
+        } else if let Some(freq) = trimmed.strip_prefix("freq:") {
+            bss.freq = freq.trim().parse::<f64>().ok().map(|mhz| mhz as u32);
+        } else if let Some(channel) = trimmed.strip_prefix("DS Parameter set: channel") {
+            bss.channel = channel.trim().to_string();
+        } else if let Some(channel) = trimmed.strip_prefix("* primary channel:") {
+            if bss.channel.is_empty() {
+                bss.channel = channel.trim().to_string();
+            }
+        } else if trimmed.starts_with("capability:") {
+            bss.privacy = trimmed.contains("Privacy");
+        } else if trimmed.starts_with("RSN:") || trimmed.starts_with("WPA:") {
+            // iw prints the first sub-field on the same line: "RSN:\t * Version: 1"
+            section = if trimmed.starts_with("RSN:") { "RSN" } else { "WPA" };
+            bss.suites.push((section, String::new()));
+        } else if let Some(suites) = trimmed.strip_prefix("* Authentication suites:") {
+            if let Some((_, auth)) = bss.suites.iter_mut().rev().find(|(name, _)| *name == section) {
+                *auth = suites.trim().to_string();
+            }
+        }
+    }
+    networks.extend(current.map(IwBss::finish));
+    networks
+}
+
+#[derive(Default)]
+struct IwBss {
+    mac: String,
+    ssid: String,
+    signal: String,
+    channel: String,
+    freq: Option<u32>,
+    privacy: bool,
+    // ("RSN" or "WPA", authentication suites)
+    suites: Vec<(&'static str, String)>,
+}
+
+impl IwBss {
+    fn finish(self) -> tokio_wifiscanner::Wifi {
+        let channel = match (self.channel.is_empty(), self.freq) {
+            // The frequency itself keeps 6 GHz channels apart from 5 GHz ones with the same number
+            (true, Some(freq)) => freq.to_string(),
+            _ => self.channel,
+        };
+        let security = if !self.suites.is_empty() {
+            self.suites.iter().map(|(name, auth)| format!("{}({})", name, auth)).collect::<Vec<_>>().join(" ")
+        } else if self.privacy {
+            "WEP".to_string()
+        } else {
+            String::new()
+        };
+        tokio_wifiscanner::Wifi { mac: self.mac, ssid: self.ssid, channel, signal_level: self.signal, security }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IW_SCAN: &str = "\
+BSS 0a:1b:2c:3d:4e:5f(on wlan0) -- associated
+\tfreq: 5180
+\tcapability: ESS Privacy ShortSlotTime (0x0411)
+\tsignal: -48.00 dBm
+\tSSID: Office
+\tRSN:\t * Version: 1
+\t\t * Authentication suites: PSK SAE
+BSS 10:e9:92:00:00:01(on wlan0)
+\tfreq: 2437
+\tcapability: ESS ShortSlotTime (0x0401)
+\tsignal: -71.00 dBm
+\tSSID: 
+\tDS Parameter set: channel 6
+";
+
+    #[test]
+    fn hardware_ports_keep_only_wifi_devices() {
+        let output = "\
+Hardware Port: Ethernet
+Device: en0
+Ethernet Address: 00:1b:63:84:45:e6
+
+Hardware Port: Wi-Fi
+Device: en1
+Ethernet Address: 00:1b:63:84:45:e7
+
+Hardware Port: Thunderbolt Bridge
+Device: bridge0
+Ethernet Address: 82:00:00:00:00:01
+";
+        assert_eq!(parse_hardware_ports(output), ["en1"]);
+        assert_eq!(parse_hardware_ports("Hardware Port: AirPort\nDevice: en1\n"), ["en1"]);
+    }
+
+    #[test]
+    fn parse_iw_scan_reads_security_and_hidden_networks() {
+        let networks = parse_iw_scan(IW_SCAN);
+        assert_eq!(networks.len(), 2);
+        assert_eq!(networks[0].mac, "0a:1b:2c:3d:4e:5f");
+        assert_eq!(networks[0].channel, "5180");
+        assert_eq!(networks[0].signal_level, "-48.00");
+        assert_eq!(networks[0].security, "RSN(PSK SAE)");
+        assert_eq!(networks[1].ssid, "");
+        assert_eq!(networks[1].channel, "6");
+        assert_eq!(networks[1].security, "");
+    }
+}
@@ -0,0 +1,2688 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use tokio::time::Instant;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
+use tracing::{debug, info, warn};
+
+#[cfg(feature = "gpsd")]
+pub mod gpsd;
+pub mod interface;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+pub mod oui;
+#[cfg(feature = "update-oui")]
+pub mod oui_update;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(feature = "webhook")]
+pub mod webhook;
+
+pub use oui::{ManufacturerTrie, OuiDatabase};
+
+#[derive(Debug)]
+pub enum ScanError {
+    ConfigRead(io::Error),
+    ConfigParse(serde_json::Error),
+    ConfigParseToml(toml::de::Error),
+    ConfigInvalid(String),
+    OuiRead(io::Error),
+    OuiParse(csv::Error),
+    OuiFormat(String),
+    LabelsRead(io::Error),
+    LabelsParse(csv::Error),
+    Scan(tokio_wifiscanner::Error),
+    Timeout(std::time::Duration),
+    Interface(String),
+    Serialize(serde_json::Error),
+    Output(io::Error),
+    ResultsRead(io::Error),
+    ResultsParse(serde_json::Error),
+    #[cfg(feature = "sqlite")]
+    Database(rusqlite::Error),
+    #[cfg(feature = "mqtt")]
+    Mqtt(rumqttc::ClientError),
+    #[cfg(feature = "webhook")]
+    Webhook(reqwest::Error),
+    #[cfg(feature = "update-oui")]
+    OuiDownload(reqwest::Error),
+    #[cfg(feature = "gpsd")]
+    Gps(io::Error),
+}
+
+impl ScanError {
+    // Process exit status for the failure: 3 config, 4 OUI database, 5 scan, 6 output
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            ScanError::ConfigRead(_) | ScanError::ConfigParse(_) | ScanError::ConfigParseToml(_) | ScanError::ConfigInvalid(_) => 3,
+            ScanError::ResultsRead(_) | ScanError::ResultsParse(_) => 3,
+            ScanError::OuiRead(_) | ScanError::OuiParse(_) | ScanError::OuiFormat(_) => 4,
+            ScanError::LabelsRead(_) | ScanError::LabelsParse(_) => 4,
+            #[cfg(feature = "update-oui")]
+            ScanError::OuiDownload(_) => 4,
+            ScanError::Scan(_) | ScanError::Timeout(_) | ScanError::Interface(_) => 5,
+            #[cfg(feature = "gpsd")]
+            ScanError::Gps(_) => 5,
+            ScanError::Serialize(_) | ScanError::Output(_) => 6,
+            #[cfg(feature = "sqlite")]
+            ScanError::Database(_) => 6,
+            #[cfg(feature = "mqtt")]
+            ScanError::Mqtt(_) => 6,
+            #[cfg(feature = "webhook")]
+            ScanError::Webhook(_) => 6,
+        }
+    }
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScanError::ConfigRead(e) => write!(f, "Could not read config file: {}", e),
+            ScanError::ConfigParse(e) => write!(f, "Could not parse config file: {}", e),
+            ScanError::ConfigParseToml(e) => write!(f, "Could not parse TOML config file: {}", e),
+            ScanError::ConfigInvalid(reason) => write!(f, "Invalid config: {}", reason),
+            ScanError::OuiRead(e) => write!(f, "Could not read OUI database: {}", e),
+            ScanError::OuiParse(e) => write!(f, "Could not parse OUI database: {}", e),
+            ScanError::OuiFormat(reason) => write!(f, "Unexpected OUI database layout: {}", reason),
+            ScanError::LabelsRead(e) => write!(f, "Could not read custom labels: {}", e),
+            ScanError::LabelsParse(e) => write!(f, "Could not parse custom labels: {}", e),
+            ScanError::Scan(e) => write!(f, "WiFi scan failed: {}", e),
+            ScanError::Interface(reason) => write!(f, "Wireless interface error: {}", reason),
+            ScanError::Timeout(limit) => write!(f, "WiFi scan did not finish within {} seconds", limit.as_secs()),
+            ScanError::Serialize(e) => write!(f, "Could not serialize results: {}", e),
+            ScanError::Output(e) => write!(f, "Could not write output file: {}", e),
+            ScanError::ResultsRead(e) => write!(f, "Could not read previous results: {}", e),
+            ScanError::ResultsParse(e) => write!(f, "Could not parse previous results: {}", e),
+            #[cfg(feature = "sqlite")]
+            ScanError::Database(e) => write!(f, "Could not write to SQLite database: {}", e),
+            #[cfg(feature = "mqtt")]
+            ScanError::Mqtt(e) => write!(f, "Could not publish to MQTT broker: {}", e),
+            #[cfg(feature = "webhook")]
+            ScanError::Webhook(e) => write!(f, "Could not deliver webhook: {}", e),
+            #[cfg(feature = "update-oui")]
+            ScanError::OuiDownload(e) => write!(f, "Could not download OUI registry: {}", e),
+            #[cfg(feature = "gpsd")]
+            ScanError::Gps(e) => write!(f, "Could not read position from gpsd: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ScanError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ScanError::ConfigRead(e) | ScanError::OuiRead(e) | ScanError::LabelsRead(e) | ScanError::Output(e) => Some(e),
+            ScanError::ResultsRead(e) => Some(e),
+            ScanError::ConfigParse(e) | ScanError::Serialize(e) | ScanError::ResultsParse(e) => Some(e),
+            ScanError::OuiParse(e) | ScanError::LabelsParse(e) => Some(e),
+            ScanError::ConfigParseToml(e) => Some(e),
+            ScanError::Scan(e) => Some(e),
+            ScanError::ConfigInvalid(_) | ScanError::OuiFormat(_) | ScanError::Timeout(_) | ScanError::Interface(_) => None,
+            #[cfg(feature = "sqlite")]
+            ScanError::Database(e) => Some(e),
+            #[cfg(feature = "mqtt")]
+            ScanError::Mqtt(e) => Some(e),
+            #[cfg(feature = "webhook")]
+            ScanError::Webhook(e) => Some(e),
+            #[cfg(feature = "update-oui")]
+            ScanError::OuiDownload(e) => Some(e),
+            #[cfg(feature = "gpsd")]
+            ScanError::Gps(e) => Some(e),
+        }
+    }
+}
+
+// Fields missing from an older results file are filled with their defaults when it's read back
+#[derive(Serialize, JsonSchema, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct WifiData {
+    // Best-effort text, with invalid UTF-8 replaced by U+FFFD
+    pub ssid: String,
+    // Raw SSID bytes in hex, set when they aren't all printable UTF-8 (control bytes, invalid sequences)
+    pub ssid_hex: Option<String>,
+    pub hidden: bool,
+    pub mac: String,
+    // The custom label when there is one, otherwise the OUI registry name or a placeholder
+    pub manufacturer: Option<String>,
+    // True when the MAC matched the OUI registry, whether or not a label overrides `manufacturer`
+    pub manufacturer_resolved: bool,
+    // The registry name cleaned up by `normalize_vendor` for grouping; unset without a registry match
+    pub vendor: Option<String>,
+    // From `custom_labels`, for an exact MAC match
+    pub label: Option<String>,
+    pub network_security: String,
+    pub security: Security,
+    pub security_raw: String,
+    pub channel: i32,
+    pub channels: Vec<i32>,
+    // Channels in the order the device moved through them, repeating one only after a change, e.g. [1, 6, 1].
+    // A scheduled scan follows the device across cycles; otherwise it's just `channel`.
+    pub channels_seen: Vec<i32>,
+    pub band: String,
+    // Centre frequency of `channel`, or the one the backend reported in its place
+    pub frequency_mhz: Option<u32>,
+    pub signal_dbm: Option<i32>,
+    pub signal_min: Option<i32>,
+    pub signal_max: Option<i32>,
+    pub signal_avg: Option<f64>,
+    // "strong", "medium" or "weak" in place of the dBm fields when `signal_bucketing` is "coarse"
+    pub signal_bucket: Option<String>,
+    pub randomized: bool,
+    // Comma separated presence windows in `timestamp_format`: "start-end" seconds ago or "start/end" RFC3339
+    pub wifi_durations: String,
+    // Wall-clock first and last sighting, only known in scheduled mode
+    pub first_seen: Option<DateTime<Utc>>,
+    pub last_seen: Option<DateTime<Utc>>,
+    // Summed length of the (merged) presence windows, only known in scheduled mode
+    pub total_dwell_secs: Option<u64>,
+    // Scan cycles the device was seen in, and that count over all cycles run; only known in scheduled mode
+    pub sighting_count: Option<u64>,
+    pub stability: Option<f64>,
+    // Where the scanner was, when a static position or gpsd is configured
+    pub location: Option<Location>,
+    // Radio that saw the network, when `interface` or `interfaces` names them
+    pub interface: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: Option<f64>,
+    // When the GPS took the fix; unset for a static position
+    pub fix_time: Option<DateTime<Utc>>,
+}
+
+// Every field may be omitted, so `{}` is a valid config. Defaults:
+//   instant_scan: true, start_after_duration: 0, scan_duration: 60,
+//   timestamp_format: "relative", output_shape: "map", output_format: "json", pretty: true, append: false,
+//   compress: false, keep_unknown_signal: true,
+//   sanitize: "control_chars_only", signal_bucketing: "exact", dedupe_by: "none", sort_by: "mac",
+//   scan_retries: 3, scan_count: 1, scan_timeout_secs: 30, monitor_interval_secs: 60,
+//   poll_interval_secs: 5, absence_threshold_secs: 15, merge_gap_secs: 0, report_departures: false,
+//   webhook_timeout_secs: 10, webhook_retries: 3; the rest are unset
+#[derive(Deserialize)]
+pub struct Config {
+    #[serde(default = "default_instant_scan")]
+    pub instant_scan: bool,
+    // Cron expression that repeats the instant or scheduled scan at fixed times, staying resident in between,
+    // e.g. "0 0 * * * *" (seconds first) or the 5-field "0 * * * *" for every hour on the hour
+    pub schedule: Option<String>,
+    // Countdown before a scheduled scan starts
+    #[serde(default)]
+    pub start_after_duration: u64,
+    // Length of a scheduled scan
+    #[serde(default = "default_scan_duration")]
+    pub scan_duration: u64,
+    // Default output target when --output isn't given; "sqlite://path.db" writes to a database
+    pub output: Option<String>,
+    // Without an `output`, each run writes a new wifi_{mode}_{YYYYMMDD_HHMMSS} file here, creating it if needed.
+    // Monitor mode and a `schedule` always do, and only take an `output` of "-" or "sqlite://..."
+    pub output_dir: Option<String>,
+    #[serde(default)]
+    pub timestamp_format: TimestampFormat,
+    #[serde(default)]
+    pub output_shape: OutputShape,
+    #[serde(default)]
+    pub output_format: OutputFormat,
+    // Indented JSON documents; turn off for long monitor runs to save disk space
+    #[serde(default = "default_pretty")]
+    pub pretty: bool,
+    // Add to an existing NDJSON or CSV output file instead of replacing it
+    #[serde(default)]
+    pub append: bool,
+    // Gzip every output file, adding ".gz" to its name; a name that already ends in ".gz" is compressed regardless
+    #[serde(default)]
+    pub compress: bool,
+    pub ssid_filter: Option<Vec<String>>,
+    pub channel_filter: Option<Vec<i32>>,
+    // Vendors to keep or drop once the OUI lookup has run, e.g. ["Apple", "Samsung"]. Each entry matches the
+    // normalized vendor or the full manufacturer name case-insensitively, placeholders such as "Unknown" included.
+    pub manufacturer_include: Option<Vec<String>>,
+    pub manufacturer_exclude: Option<Vec<String>>,
+    // Networks weaker than this are dropped; ones whose signal doesn't parse follow `keep_unknown_signal`
+    pub min_signal_dbm: Option<i32>,
+    // A scheduled scan ends as soon as this MAC, or any network named this SSID, shows up
+    pub stop_on_mac: Option<String>,
+    pub stop_on_ssid: Option<String>,
+    #[serde(default = "default_keep_unknown_signal")]
+    pub keep_unknown_signal: bool,
+    #[serde(default = "default_scan_retries")]
+    pub scan_retries: u32,
+    // Back-to-back scans an instant or monitor cycle unions by BSSID, keeping each one's strongest reading
+    #[serde(default = "default_scan_count")]
+    pub scan_count: u32,
+    // Longest a single scan attempt may take before it's abandoned
+    #[serde(default = "default_scan_timeout_secs")]
+    pub scan_timeout_secs: u64,
+    #[serde(default = "default_monitor_interval_secs")]
+    pub monitor_interval_secs: u64,
+    // Pause between scans in scheduled mode
+    #[serde(default = "default_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+    // A device unseen for longer than this has left. The gap between sightings is the poll interval plus
+    // however long the scan took, so this must be greater than `poll_interval_secs`, with room to spare.
+    #[serde(default = "default_absence_threshold_secs")]
+    pub absence_threshold_secs: u64,
+    // Presence windows closer together than this are reported as one
+    #[serde(default)]
+    pub merge_gap_secs: u64,
+    // Bounds a long scheduled scan's memory: presence windows that ended, and devices last seen, longer ago
+    // than this are dropped each cycle, so the report only covers that much history. Unset keeps everything.
+    pub history_retention_secs: Option<u64>,
+    // Monitor mode reports a device as departed once it's gone for `absence_threshold_secs`, which must then be at
+    // least `monitor_interval_secs`; set it above that so a single missed scan isn't a departure
+    #[serde(default)]
+    pub report_departures: bool,
+    #[serde(default)]
+    pub sanitize: SanitizeMode,
+    // Coarser signal readings, for privacy or steadier diffs between scans; filters, max_results and
+    // sort_by still see the exact dBm
+    #[serde(default)]
+    pub signal_bucketing: SignalBucketing,
+    // Report each MAC as `hashed_mac(mac, mac_salt)` instead. The OUI lookup, custom labels and the randomized
+    // flag still see the real address, and a fixed salt maps a device to the same value on every run, so
+    // presence tracking, departures and --compare keep working. That consistency is the tradeoff: the hash
+    // is still a stable identifier that can follow a device over time, and with only 2^48 MACs (far fewer in
+    // practice) anyone holding the salt can recover one by hashing candidates. Keep the salt secret
+    // (WIFI_MAC_SALT keeps it out of the file), and change it to unlink new results from old ones. SSIDs and
+    // manufacturers are written as usual.
+    #[serde(default)]
+    pub hash_mac: bool,
+    pub mac_salt: Option<String>,
+    #[serde(default)]
+    pub dedupe_by: DedupeBy,
+    #[serde(default)]
+    pub sort_by: SortBy,
+    // Keep only the N strongest networks
+    pub max_results: Option<usize>,
+    // CSV of "mac,label" rows naming specific devices. A label is reported as `label` and replaces the OUI
+    // name in `manufacturer`; `vendor` and `manufacturer_resolved` still come from the registry.
+    pub custom_labels: Option<String>,
+    // Wireless interface to scan, e.g. "wlan1"; the platform default when unset
+    pub interface: Option<String>,
+    // Several radios to scan concurrently, on top of `interface`
+    #[serde(default)]
+    pub interfaces: Vec<String>,
+    // Channels to tune the named interfaces to, one per scan in rotation (Linux `iw` only); empty scans all
+    #[serde(default)]
+    pub channels_to_hop: Vec<i32>,
+    // Static position stamped on every record, for a scanner that doesn't move
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub altitude: Option<f64>,
+    // Address of a gpsd to read live positions from, e.g. "127.0.0.1:2947"; only used with the gpsd feature
+    pub gpsd: Option<String>,
+    // Only used when built with the mqtt feature
+    pub mqtt: Option<MqttConfig>,
+    // Only used when built with the webhook feature
+    pub webhook_url: Option<String>,
+    #[serde(default = "default_webhook_timeout_secs")]
+    pub webhook_timeout_secs: u64,
+    #[serde(default = "default_webhook_retries")]
+    pub webhook_retries: u32,
+    // Only used when built with the metrics feature
+    pub metrics_port: Option<u16>,
+}
+
+#[derive(Deserialize)]
+pub struct MqttConfig {
+    pub broker: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    pub topic: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+fn default_instant_scan() -> bool {
+    true
+}
+
+fn default_pretty() -> bool {
+    true
+}
+
+fn default_keep_unknown_signal() -> bool {
+    true
+}
+
+fn default_scan_duration() -> u64 {
+    60
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_webhook_timeout_secs() -> u64 {
+    10
+}
+
+fn default_webhook_retries() -> u32 {
+    3
+}
+
+fn default_scan_retries() -> u32 {
+    3
+}
+
+fn default_scan_count() -> u32 {
+    1
+}
+
+fn default_scan_timeout_secs() -> u64 {
+    30
+}
+
+fn default_monitor_interval_secs() -> u64 {
+    60
+}
+
+fn default_poll_interval_secs() -> u64 {
+    5
+}
+
+// Three polls at the default interval, so one slow or missed scan doesn't split a presence window
+fn default_absence_threshold_secs() -> u64 {
+    15
+}
+
+impl Config {
+    // `interface` followed by `interfaces`, without repeats; empty means the platform default
+    pub fn scan_interfaces(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for name in self.interface.iter().chain(&self.interfaces) {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+        names
+    }
+
+    // The configured static position, if any
+    pub fn static_location(&self) -> Option<Location> {
+        Some(Location {
+            latitude: self.latitude?,
+            longitude: self.longitude?,
+            altitude: self.altitude,
+            fix_time: None,
+        })
+    }
+
+    // Rejects settings that would make a run a silent no-op
+    pub fn validate(&self) -> Result<(), ScanError> {
+        let zero_durations = [
+            ("scan_duration", self.scan_duration),
+            ("scan_timeout_secs", self.scan_timeout_secs),
+            ("poll_interval_secs", self.poll_interval_secs),
+            ("monitor_interval_secs", self.monitor_interval_secs),
+            ("webhook_timeout_secs", self.webhook_timeout_secs),
+        ];
+        for (field, value) in zero_durations {
+            if value == 0 {
+                return Err(ScanError::ConfigInvalid(format!("{} must be greater than 0", field)));
+            }
+        }
+        if self.append && !matches!(self.output_format, OutputFormat::Ndjson | OutputFormat::Csv | OutputFormat::Wigle) {
+            return Err(ScanError::ConfigInvalid("append only works with the ndjson, csv and wigle output formats".to_string()));
+        }
+        match (self.latitude, self.longitude) {
+            (Some(latitude), Some(longitude)) if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) => {
+                return Err(ScanError::ConfigInvalid("latitude or longitude is out of range".to_string()));
+            }
+            (Some(_), None) | (None, Some(_)) => {
+                return Err(ScanError::ConfigInvalid("latitude and longitude must be set together".to_string()));
+            }
+            _ => {}
+        }
+        if !self.channels_to_hop.is_empty() && self.scan_interfaces().is_empty() {
+            return Err(ScanError::ConfigInvalid("channels_to_hop needs an `interface` or `interfaces` to tune".to_string()));
+        }
+        if let Some(channel) = self.channels_to_hop.iter().find(|&&channel| channel_to_frequency(channel).is_none()) {
+            return Err(ScanError::ConfigInvalid(format!("channels_to_hop has {}, which isn't a WiFi channel", channel)));
+        }
+        if self.history_retention_secs == Some(0) {
+            return Err(ScanError::ConfigInvalid("history_retention_secs must be greater than 0".to_string()));
+        }
+        if self.hash_mac && self.mac_salt.as_deref().is_none_or(str::is_empty) {
+            return Err(ScanError::ConfigInvalid("hash_mac needs a mac_salt, an unsalted MAC hash is trivial to reverse".to_string()));
+        }
+        self.cron_schedule()?;
+        if self.scan_count == 0 {
+            return Err(ScanError::ConfigInvalid("scan_count must be greater than 0".to_string()));
+        }
+        if self.max_results == Some(0) {
+            return Err(ScanError::ConfigInvalid("max_results must be greater than 0".to_string()));
+        }
+        // Consecutive sightings are at least a poll interval apart, so an equal threshold trips on any jitter
+        if self.absence_threshold_secs <= self.poll_interval_secs {
+            return Err(ScanError::ConfigInvalid(format!(
+                "absence_threshold_secs ({}) must be greater than poll_interval_secs ({})",
+                self.absence_threshold_secs, self.poll_interval_secs
+            )));
+        }
+        // Departures are only checked once a monitor cycle, so a shorter threshold makes one missed scan a departure
+        if self.report_departures && self.absence_threshold_secs < self.monitor_interval_secs {
+            return Err(ScanError::ConfigInvalid(format!(
+                "report_departures needs absence_threshold_secs ({}) to be at least monitor_interval_secs ({})",
+                self.absence_threshold_secs, self.monitor_interval_secs
+            )));
+        }
+        Ok(())
+    }
+
+    // The parsed `schedule`; a 5-field crontab line is read as firing at second 0
+    pub fn cron_schedule(&self) -> Result<Option<cron::Schedule>, ScanError> {
+        let Some(expression) = self.schedule.as_deref() else {
+            return Ok(None);
+        };
+        let expression = match expression.split_whitespace().count() {
+            5 => format!("0 {}", expression),
+            _ => expression.to_string(),
+        };
+        expression.parse().map(Some).map_err(|e| ScanError::ConfigInvalid(format!("schedule \"{}\" isn't a cron expression: {}", expression, e)))
+    }
+
+    // Whether a normalized MAC or decoded SSID is what `stop_on_mac` or `stop_on_ssid` is waiting for
+    pub fn is_watch_target(&self, mac: &str, ssid: &str) -> bool {
+        self.stop_on_mac.as_deref().is_some_and(|target| normalize_mac(target) == mac)
+            || self.stop_on_ssid.as_deref().is_some_and(|target| target == ssid)
+    }
+
+    // Set when a scheduled scan watches for a device
+    pub fn has_watch_target(&self) -> bool {
+        self.stop_on_mac.is_some() || self.stop_on_ssid.is_some()
+    }
+
+    // An absent or empty include list keeps every vendor; the exclude list is applied after it
+    fn keeps_manufacturer(&self, item: &WifiData) -> bool {
+        let matches = |names: &[String]| {
+            let manufacturer = item.manufacturer.as_deref().unwrap_or_default();
+            names.iter().any(|name| {
+                name.eq_ignore_ascii_case(manufacturer)
+                    || item.vendor.as_deref().is_some_and(|vendor| normalize_vendor(name).eq_ignore_ascii_case(vendor))
+            })
+        };
+        let included = match &self.manufacturer_include {
+            Some(names) if !names.is_empty() => matches(names),
+            _ => true,
+        };
+        included && !self.manufacturer_exclude.as_deref().is_some_and(matches)
+    }
+
+    // An absent or empty filter keeps every network; SSIDs match case-insensitively
+    fn keeps(&self, network: &tokio_wifiscanner::Wifi) -> bool {
+        let ssid_ok = match &self.ssid_filter {
+            Some(ssids) if !ssids.is_empty() => {
+                let ssid = ssid_text(&network.ssid).to_lowercase();
+                ssids.iter().any(|wanted| wanted.to_lowercase() == ssid)
+            }
+            _ => true,
+        };
+        let channel_ok = match &self.channel_filter {
+            Some(channels) if !channels.is_empty() => channels.contains(&channel_and_frequency(&network.channel).0),
+            _ => true,
+        };
+        let signal_ok = match (self.min_signal_dbm, parse_signal_dbm(&network.signal_level)) {
+            (Some(floor), Some(dbm)) => dbm >= floor,
+            (Some(_), None) => self.keep_unknown_signal,
+            (None, _) => true,
+        };
+        ssid_ok && channel_ok && signal_ok
+    }
+}
+
+// How presence windows are written into `wifi_durations`
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TimestampFormat {
+    // Seconds elapsed since each sighting, e.g. "42-12"
+    #[default]
+    Relative,
+    // RFC3339 start/end pairs, e.g. "2024-01-01T12:00:00Z/2024-01-01T12:00:30Z"
+    Absolute,
+}
+
+// How the records sit in a JSON results document
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputShape {
+    // Keyed by 1-based position, "1": {...}, "2": {...}, next to the summary sections
+    #[default]
+    Map,
+    // A plain array under "results"
+    Array,
+}
+
+// How SSIDs and manufacturer names are cleaned before they're written out
+#[derive(Deserialize, Clone, Copy, Default, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SanitizeMode {
+    // Exactly what the backend reported
+    None,
+    // Drop control characters; serde_json and csv already escape quotes
+    #[default]
+    ControlCharsOnly,
+    // Letters, digits, spaces and "-_." only, for consumers that splice values into SQL or shell commands
+    Aggressive,
+}
+
+impl SanitizeMode {
+    pub fn apply(self, input: &str) -> String {
+        match self {
+            SanitizeMode::None => input.to_string(),
+            SanitizeMode::ControlCharsOnly => input.chars().filter(|c| !c.is_control()).collect(),
+            SanitizeMode::Aggressive => input.chars()
+                .filter(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.'))
+                .collect(),
+        }
+    }
+}
+
+// How signal readings are written: "exact", "coarse" or "bins:N"
+#[derive(Deserialize, Clone, Copy, Default, Debug, PartialEq)]
+#[serde(try_from = "String")]
+pub enum SignalBucketing {
+    // The dBm the backend reported
+    #[default]
+    Exact,
+    // `signal_bucket` only, split at `STRONG_SIGNAL_DBM` and `MEDIUM_SIGNAL_DBM`
+    Coarse,
+    // dBm rounded down to a multiple of N, e.g. "bins:5" reports -63 as -65
+    Bins(u32),
+}
+
+// Weakest readings still reported as "strong" and "medium"
+const STRONG_SIGNAL_DBM: i32 = -60;
+const MEDIUM_SIGNAL_DBM: i32 = -75;
+
+impl TryFrom<String> for SignalBucketing {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, String> {
+        match value.as_str() {
+            "exact" => Ok(SignalBucketing::Exact),
+            "coarse" => Ok(SignalBucketing::Coarse),
+            other => match other.strip_prefix("bins:").map(|width| width.trim().parse::<u32>()) {
+                Some(Ok(width)) if width > 0 => Ok(SignalBucketing::Bins(width)),
+                _ => Err(format!("signal_bucketing \"{}\" isn't exact, coarse or bins:N with N above 0", value)),
+            },
+        }
+    }
+}
+
+impl SignalBucketing {
+    pub fn apply(self, item: &mut WifiData) {
+        match self {
+            SignalBucketing::Exact => {}
+            SignalBucketing::Coarse => {
+                item.signal_bucket = item.signal_dbm.map(|dbm| {
+                    match dbm {
+                        dbm if dbm >= STRONG_SIGNAL_DBM => "strong",
+                        dbm if dbm >= MEDIUM_SIGNAL_DBM => "medium",
+                        _ => "weak",
+                    }
+                    .to_string()
+                });
+                (item.signal_dbm, item.signal_min, item.signal_max, item.signal_avg) = (None, None, None, None);
+            }
+            SignalBucketing::Bins(width) => {
+                let width = width as i32;
+                let round = |dbm: i32| dbm.div_euclid(width) * width;
+                item.signal_dbm = item.signal_dbm.map(round);
+                item.signal_min = item.signal_min.map(round);
+                item.signal_max = item.signal_max.map(round);
+                item.signal_avg = item.signal_avg.map(|avg| (avg / width as f64).floor() * width as f64);
+            }
+        }
+    }
+}
+
+// Normalized form of the backend's free-text security string
+#[derive(Serialize, JsonSchema, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Security {
+    Open,
+    // Enhanced Open: no password, but the traffic is encrypted
+    #[serde(rename = "OWE")]
+    Owe,
+    #[serde(rename = "WEP")]
+    Wep,
+    #[serde(rename = "WPA")]
+    Wpa,
+    #[serde(rename = "WPA2")]
+    Wpa2,
+    #[serde(rename = "WPA3")]
+    Wpa3,
+    #[serde(rename = "WPA2/WPA3")]
+    Wpa2Wpa3Mixed,
+    #[default]
+    Unknown,
+}
+
+impl Security {
+    // Same label the JSON output uses
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Security::Open => "Open",
+            Security::Owe => "OWE",
+            Security::Wep => "WEP",
+            Security::Wpa => "WPA",
+            Security::Wpa2 => "WPA2",
+            Security::Wpa3 => "WPA3",
+            Security::Wpa2Wpa3Mixed => "WPA2/WPA3",
+            Security::Unknown => "Unknown",
+        }
+    }
+
+    // Handles the airport ("WPA2(PSK/AES/AES)", "RSN(PSK,SAE/AES/AES)") and netsh ("WPA2-Personal") spellings.
+    // OWE ("RSN(OWE)" from iw) is checked first so Enhanced Open networks aren't counted as WPA2 or Open.
+    // An empty string is Open, matching `network_security`, although the Linux backend never fills it in.
+    pub fn parse(raw: &str) -> Security {
+        let upper = raw.trim().to_uppercase();
+        let wpa3 = upper.contains("WPA3") || upper.contains("SAE");
+        if upper.contains("OWE") && !wpa3 && !upper.contains("PSK") {
+            Security::Owe
+        } else if wpa3 && (upper.contains("WPA2") || upper.contains("PSK")) {
+            Security::Wpa2Wpa3Mixed
+        } else if wpa3 {
+            Security::Wpa3
+        } else if upper.contains("WPA2") || upper.contains("RSN") {
+            Security::Wpa2
+        } else if upper.contains("WPA") {
+            Security::Wpa
+        } else if upper.contains("WEP") {
+            Security::Wep
+        } else if upper.is_empty() || upper == "NONE" || upper == "OPEN" {
+            Security::Open
+        } else {
+            Security::Unknown
+        }
+    }
+}
+
+// How instant-mode rows describing the same AP are collapsed
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DedupeBy {
+    Bssid,
+    Ssid,
+    #[default]
+    None,
+}
+
+// Order of the records before they're numbered; ties fall back to MAC so runs are reproducible
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortBy {
+    #[default]
+    Mac,
+    Ssid,
+    // Strongest first, unknown signal last
+    Signal,
+    Channel,
+}
+
+// Which result files are written after a scan
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Json,
+    Csv,
+    Both,
+    // One compact WifiData object per line
+    Ndjson,
+    // WigleWifi-1.4 CSV for uploading to wigle.net
+    Wigle,
+    // One placemark per located AP, for Google Earth and other map viewers
+    Kml,
+}
+
+impl OutputFormat {
+    // Extension of the primary file; `Both` writes its CSV next to the JSON
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Json | OutputFormat::Both => "json",
+            OutputFormat::Csv | OutputFormat::Wigle => "csv",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Kml => "kml",
+        }
+    }
+}
+
+// Several named configs in one file, e.g. `{ "profiles": { "quick": {...}, "overnight": {...} }, "default": "quick" }`
+#[derive(Deserialize)]
+struct ProfileFile {
+    profiles: HashMap<String, serde_json::Value>,
+    default: Option<String>,
+}
+
+impl ProfileFile {
+    fn select(mut self, profile: Option<&str>) -> Result<serde_json::Value, ScanError> {
+        let name = match profile.or(self.default.as_deref()) {
+            Some(name) => name.to_string(),
+            None => return Err(ScanError::ConfigInvalid("no profile selected; pass --profile or set \"default\"".to_string())),
+        };
+        self.profiles.remove(&name).ok_or_else(|| {
+            let mut known: Vec<&String> = self.profiles.keys().collect();
+            known.sort();
+            let known = known.iter().map(|name| name.as_str()).collect::<Vec<_>>().join(", ");
+            ScanError::ConfigInvalid(format!("unknown profile \"{}\" (available: {})", name, known))
+        })
+    }
+}
+
+// Accepts either a flat config or the profiles shape; `profile` picks one of the latter
+pub fn read_config(filename: &str, profile: Option<&str>) -> Result<Config, ScanError> {
+    read_config_with(filename, profile, |_| {})
+}
+
+// Written out when the config file is missing. JSON has no comments, so the `_comment` keys (ignored
+// when parsing) carry the explanations.
+pub const DEFAULT_CONFIG: &str = r#"{
+    "_comment": "Every field is optional; delete one to fall back to its default",
+    "_comment_mode": "instant_scan: one scan and exit; false runs a scheduled scan of scan_duration seconds after start_after_duration",
+    "instant_scan": true,
+    "start_after_duration": 0,
+    "scan_duration": 60,
+    "poll_interval_secs": 5,
+    "_comment_output": "output_format: json, csv, both, ndjson, wigle or kml; timestamp_format: relative or absolute; output_shape: map (numbered keys) or array",
+    "output_format": "json",
+    "timestamp_format": "relative",
+    "output_shape": "map",
+    "pretty": true,
+    "append": false,
+    "compress": false,
+    "_comment_filters": "Optional: ssid_filter [\"name\"], channel_filter [1, 6, 11], max_results, manufacturer_include [\"Apple\"], interface \"wlan0\"",
+    "sanitize": "control_chars_only",
+    "_comment_signal": "signal_bucketing: exact, coarse (strong/medium/weak) or bins:N (dBm rounded down to a multiple of N)",
+    "signal_bucketing": "exact",
+    "dedupe_by": "none",
+    "sort_by": "mac",
+    "scan_retries": 3,
+    "scan_count": 1,
+    "scan_timeout_secs": 30
+}
+"#;
+
+// `DEFAULT_CONFIG` for a config file named *.toml, where the explanations can be real comments
+pub const DEFAULT_CONFIG_TOML: &str = r#"# Every field is optional; delete one to fall back to its default
+
+# instant_scan: one scan and exit; false runs a scheduled scan of scan_duration seconds after start_after_duration
+instant_scan = true
+start_after_duration = 0
+scan_duration = 60
+poll_interval_secs = 5
+
+# output_format: json, csv, both, ndjson, wigle or kml; timestamp_format: relative or absolute; output_shape: map (numbered keys) or array
+output_format = "json"
+timestamp_format = "relative"
+output_shape = "map"
+pretty = true
+append = false
+compress = false
+
+# Optional: ssid_filter = ["name"], channel_filter = [1, 6, 11], max_results, manufacturer_include = ["Apple"], interface = "wlan0"
+sanitize = "control_chars_only"
+# signal_bucketing: exact, coarse (strong/medium/weak) or bins:N (dBm rounded down to a multiple of N)
+signal_bucketing = "exact"
+dedupe_by = "none"
+sort_by = "mac"
+scan_retries = 3
+scan_count = 1
+scan_timeout_secs = 30
+"#;
+
+// Config files ending in .toml are read as TOML, everything else as JSON
+fn is_toml_config(filename: &str) -> bool {
+    std::path::Path::new(filename).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("toml"))
+}
+
+// Prefix of the environment variables that override config fields, e.g. WIFI_SCAN_DURATION=120
+const ENV_PREFIX: &str = "WIFI_";
+
+// Shorter spellings accepted on top of the upper-cased field names
+const ENV_ALIASES: [(&str, &str); 1] = [("START_AFTER", "start_after_duration")];
+
+// Sets `WIFI_<FIELD>` variables on top of the file's values. Each value is read as JSON (numbers,
+// booleans, arrays, objects) when that parses and suits the field, and as a plain string otherwise, so
+// WIFI_MAC_SALT=20240101 stays a string. A value the field takes in neither form is a ConfigInvalid
+// naming the variable.
+fn apply_env_overrides(config: &mut serde_json::Value, vars: impl Iterator<Item = (String, String)>) -> Result<(), ScanError> {
+    let Some(fields) = config.as_object_mut() else {
+        return Ok(());
+    };
+    // Whether `value` deserializes as `field` on its own; every other field has a default
+    let suits = |field: &str, value: &serde_json::Value| {
+        serde_json::from_value::<Config>(serde_json::json!({ field: value })).map(|_| ())
+    };
+    for (name, raw) in vars {
+        let Some(suffix) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        let field = ENV_ALIASES.iter()
+            .find(|(alias, _)| *alias == suffix)
+            .map(|(_, field)| field.to_string())
+            .unwrap_or_else(|| suffix.to_lowercase());
+        let value = match serde_json::from_str(&raw) {
+            Ok(parsed) if suits(&field, &parsed).is_ok() => parsed,
+            _ => {
+                let value = serde_json::Value::String(raw);
+                suits(&field, &value).map_err(|e| ScanError::ConfigInvalid(format!("{} isn't a valid {}: {}", name, field, e)))?;
+                value
+            }
+        };
+        debug!("{} overrides config field {}", name, field);
+        fields.insert(field, value);
+    }
+    Ok(())
+}
+
+// Like `read_config`, with `overrides` applied before validation, e.g. for command line flags.
+// Precedence is `overrides`, then WIFI_* environment variables, then the file, then the defaults.
+//
+// A missing file is replaced with `DEFAULT_CONFIG` (or `DEFAULT_CONFIG_TOML` for a .toml name) and the
+// scan goes ahead with the defaults.
+pub fn read_config_with(filename: &str, profile: Option<&str>, overrides: impl FnOnce(&mut Config)) -> Result<Config, ScanError> {
+    let toml = is_toml_config(filename);
+    let contents = match File::open(filename) {
+        Ok(mut file) => {
+            let mut contents = String::new();
+            file.read_to_string(&mut contents).map_err(ScanError::ConfigRead)?;
+            contents
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let default = if toml { DEFAULT_CONFIG_TOML } else { DEFAULT_CONFIG };
+            match std::fs::write(filename, default) {
+                Ok(()) => warn!("No config found at {}, wrote a default one there; edit it and run again to change the scan", filename),
+                Err(e) => warn!("No config found at {} and a default couldn't be written ({}), using the defaults", filename, e),
+            }
+            default.to_string()
+        }
+        Err(e) => return Err(ScanError::ConfigRead(e)),
+    };
+    // Both formats go through the same JSON value, so profiles and WIFI_* overrides work alike
+    let value: serde_json::Value = if toml {
+        toml::from_str(&contents).map_err(ScanError::ConfigParseToml)?
+    } else {
+        serde_json::from_str(&contents).map_err(ScanError::ConfigParse)?
+    };
+    let mut selected = if value.get("profiles").is_some() {
+        let file: ProfileFile = serde_json::from_value(value).map_err(ScanError::ConfigParse)?;
+        file.select(profile)?
+    } else if let Some(name) = profile {
+        return Err(ScanError::ConfigInvalid(format!("profile \"{}\" requested but {} has no profiles", name, filename)));
+    } else {
+        value
+    };
+    apply_env_overrides(&mut selected, std::env::vars())?;
+    let mut config: Config = serde_json::from_value(selected).map_err(ScanError::ConfigParse)?;
+    overrides(&mut config);
+    config.validate()?;
+    Ok(config)
+}
+
+// A single observation of a device, kept on both the monotonic and wall clocks
+#[derive(Clone, Copy)]
+struct Sighting {
+    instant: Instant,
+    timestamp: DateTime<Utc>,
+}
+
+impl Sighting {
+    fn now() -> Self {
+        Sighting { instant: Instant::now(), timestamp: Utc::now() }
+    }
+}
+
+// A device that was present and has now been unseen for longer than the absence threshold
+#[derive(Serialize, Clone, Debug)]
+pub struct Departure {
+    pub event: &'static str,
+    pub mac: String,
+    pub ssid: String,
+    pub manufacturer: Option<String>,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    // Length of the session that just ended, first to last sighting
+    pub dwell_secs: u64,
+}
+
+struct Session {
+    first_seen: Sighting,
+    last_seen: Sighting,
+    ssid: String,
+    manufacturer: Option<String>,
+}
+
+// Per-MAC sessions carried across monitor cycles
+#[derive(Default)]
+pub struct PresenceTracker {
+    sessions: HashMap<String, Session>,
+}
+
+impl PresenceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Records this cycle's sightings and returns the devices whose last sighting is now more than
+    // `absence_threshold_secs` old. A departed MAC that comes back starts a new session.
+    pub fn update(&mut self, seen: &[WifiData], absence_threshold_secs: u64) -> Vec<Departure> {
+        let now = Sighting::now();
+        for item in seen {
+            self.sessions.entry(item.mac.clone())
+                .and_modify(|session| session.last_seen = now)
+                .or_insert_with(|| Session {
+                    first_seen: now,
+                    last_seen: now,
+                    ssid: item.ssid.clone(),
+                    manufacturer: item.manufacturer.clone(),
+                });
+        }
+
+        let threshold = tokio::time::Duration::from_secs(absence_threshold_secs);
+        let departed: Vec<String> = self.sessions.iter()
+            .filter(|(_, session)| now.instant.duration_since(session.last_seen.instant) > threshold)
+            .map(|(mac, _)| mac.clone())
+            .collect();
+        let mut departures: Vec<Departure> = departed.into_iter()
+            .filter_map(|mac| {
+                let session = self.sessions.remove(&mac)?;
+                Some(Departure {
+                    event: "departed",
+                    dwell_secs: session.last_seen.instant.duration_since(session.first_seen.instant).as_secs(),
+                    first_seen: session.first_seen.timestamp,
+                    last_seen: session.last_seen.timestamp,
+                    ssid: session.ssid,
+                    manufacturer: session.manufacturer,
+                    mac,
+                })
+            })
+            .collect();
+        departures.sort_by(|a, b| a.mac.cmp(&b.mac));
+        departures
+    }
+}
+
+// Everything the scheduled scan remembers about one MAC
+struct DeviceTrack {
+    first_seen: Sighting,
+    last_seen: Sighting,
+    // Start of the presence window the device is currently in
+    window_start: Sighting,
+    // Presence windows already closed by an absence longer than the threshold
+    intervals: Vec<(Sighting, Sighting)>,
+    // Latest record, so devices that left before the final scan can still be reported
+    network: tokio_wifiscanner::Wifi,
+    signal_samples: Vec<i32>,
+    channels_seen: Vec<i32>,
+    // Scan cycles the device showed up in, and the latest one, so a repeated record isn't counted twice
+    sightings: u64,
+    last_cycle: u64,
+}
+
+impl DeviceTrack {
+    fn new(network: tokio_wifiscanner::Wifi, now: Sighting, cycle: u64) -> Self {
+        DeviceTrack {
+            first_seen: now,
+            last_seen: now,
+            window_start: now,
+            intervals: Vec::new(),
+            signal_samples: parse_signal_dbm(&network.signal_level).into_iter().collect(),
+            channels_seen: vec![channel_and_frequency(&network.channel).0],
+            network,
+            sightings: 1,
+            last_cycle: cycle,
+        }
+    }
+
+    fn observe(&mut self, network: tokio_wifiscanner::Wifi, now: Sighting, cycle: u64, absence_threshold_secs: u64) {
+        if cycle != self.last_cycle {
+            self.sightings += 1;
+            self.last_cycle = cycle;
+        }
+        // Compared at full precision; whole seconds would hide up to a second of drift
+        if now.instant.duration_since(self.last_seen.instant) > tokio::time::Duration::from_secs(absence_threshold_secs) {
+            self.intervals.push((self.window_start, self.last_seen));
+            self.window_start = now;
+        }
+        self.last_seen = now;
+        self.signal_samples.extend(parse_signal_dbm(&network.signal_level));
+        let channel = channel_and_frequency(&network.channel).0;
+        if self.channels_seen.last() != Some(&channel) {
+            self.channels_seen.push(channel);
+        }
+        self.network = network;
+    }
+
+    // Drops closed windows that ended before `cutoff`, and signal readings beyond `max_samples`, oldest first
+    fn prune(&mut self, cutoff: Instant, max_samples: usize) {
+        self.intervals.retain(|(_, end)| end.instant >= cutoff);
+        let excess = self.signal_samples.len().saturating_sub(max_samples);
+        self.signal_samples.drain(..excess);
+    }
+
+    // Every presence window including the open one, with windows less than `merge_gap_secs` apart joined
+    fn windows(&self, merge_gap_secs: u64) -> Vec<(Sighting, Sighting)> {
+        let mut merged: Vec<(Sighting, Sighting)> = Vec::new();
+        for (start, end) in self.intervals.iter().copied().chain([(self.window_start, self.last_seen)]) {
+            match merged.last_mut() {
+                Some(previous) if start.instant.duration_since(previous.1.instant) < tokio::time::Duration::from_secs(merge_gap_secs) => {
+                    previous.1 = end
+                }
+                _ => merged.push((start, end)),
+            }
+        }
+        merged
+    }
+}
+
+// Runs a single scan and enriches every network found
+pub async fn scan_once(scanner: &impl Scanner, config: &Config, oui: &OuiDatabase) -> Result<Vec<WifiData>, ScanError> {
+    let networks = scan_passes(scanner, config).await?;
+    let mut results = dedupe(convert_to_wifi_data(&networks, oui, config), config.dedupe_by);
+    tag_interfaces(&mut results, scanner);
+    finish_results(&mut results, config);
+    Ok(results)
+}
+
+// Runs `scan_once` every `interval` and yields each enriched batch, for callers embedding the scanner in
+// their own async app. The first scan starts immediately; scanning stops once the stream is dropped.
+pub fn scan_stream<S>(scanner: S, config: Config, oui: OuiDatabase, interval: std::time::Duration) -> impl Stream<Item = Result<Vec<WifiData>, ScanError>>
+where
+    S: Scanner + Send + Sync + 'static,
+{
+    let (tx, rx) = tokio::sync::mpsc::channel(1);
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            if tx.send(scan_once(&scanner, &config, &oui).await).await.is_err() {
+                break;
+            }
+        }
+    });
+    ReceiverStream::new(rx)
+}
+
+// Scans repeatedly for `config.scan_duration` seconds and reports the presence windows of each device.
+// Ctrl-C ends the scan early with what was gathered so far.
+pub async fn scan_scheduled(scanner: &impl Scanner, config: &Config, oui: &OuiDatabase) -> Result<Vec<WifiData>, ScanError> {
+    Ok(scan_scheduled_watch(scanner, config, oui, ctrl_c_pressed()).await?.0)
+}
+
+// Resolves on Ctrl-C; never, where the signal can't be listened for
+pub async fn ctrl_c_pressed() {
+    if tokio::signal::ctrl_c().await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
+// Like `scan_scheduled`, but stops at the end of the cycle that first sees `stop_on_mac` or `stop_on_ssid`,
+// and also says whether that happened. `stop` resolving (e.g. `ctrl_c_pressed()`) cancels the countdown
+// with no results, or ends the scan itself with the partial ones.
+pub async fn scan_scheduled_watch(
+    scanner: &impl Scanner,
+    config: &Config,
+    oui: &OuiDatabase,
+    stop: impl Future<Output = ()>,
+) -> Result<(Vec<WifiData>, bool), ScanError> {
+    let start_after_duration = config.start_after_duration;
+    let scan_duration = config.scan_duration;
+    tokio::pin!(stop);
+    for i in (1..=start_after_duration).rev() {
+        info!("Scan starts in {} seconds", i);
+        tokio::select! {
+            _ = tokio::time::sleep(tokio::time::Duration::from_secs(1)) => {}
+            _ = &mut stop => {
+                warn!("Scan cancelled before it started");
+                return Ok((Vec::new(), false));
+            }
+        }
+    }
+
+    info!("Scan started, it will last for {} seconds...", scan_duration);
+    let scan_start_time = Instant::now();
+    let mut tracks: HashMap<String, DeviceTrack> = HashMap::new();
+    let mut spotted = false;
+    let mut cycles: u64 = 0;
+
+    // `stop` drops the collection loop at its next await point and falls through to reporting what was gathered
+    let collect = async {
+        while Instant::now().duration_since(scan_start_time) < tokio::time::Duration::from_secs(scan_duration) {
+            cycles += 1;
+            // One failed cycle shouldn't end the whole scheduled scan
+            let networks = scan_with_retry(scanner, config).await.unwrap_or_else(|e| {
+                warn!("Scan failed, skipping this cycle: {}", e);
+                Vec::new()
+            });
+
+            for network in networks.into_iter().filter(|network| config.keeps(network)) {
+                let now = Sighting::now();
+                let mac = normalize_mac(&network.mac);
+                let ssid = ssid_text(&network.ssid);
+                if !spotted && config.is_watch_target(&mac, &ssid) {
+                    info!("Spotted {} ({}), stopping the scan", mac, ssid);
+                    spotted = true;
+                }
+                match tracks.get_mut(&mac) {
+                    Some(track) => track.observe(network, now, cycles, config.absence_threshold_secs),
+                    None => {
+                        tracks.insert(mac, DeviceTrack::new(network, now, cycles));
+                    }
+                }
+            }
+            if let Some(retention_secs) = config.history_retention_secs {
+                let retention = tokio::time::Duration::from_secs(retention_secs);
+                if let Some(cutoff) = Instant::now().checked_sub(retention) {
+                    // About one reading per poll fits in the window
+                    let max_samples = (retention_secs / config.poll_interval_secs + 1) as usize;
+                    tracks.retain(|_, track| track.last_seen.instant >= cutoff);
+                    tracks.values_mut().for_each(|track| track.prune(cutoff, max_samples));
+                }
+            }
+            if spotted {
+                break;
+            }
+            tokio::time::sleep(tokio::time::Duration::from_secs(config.poll_interval_secs)).await;
+        }
+    };
+    tokio::select! {
+        _ = collect => {}
+        _ = &mut stop => warn!("Scan interrupted, saving partial results..."),
+    }
+
+    let mut results = generate_results(&tracks, cycles, oui, config);
+    tag_interfaces(&mut results, scanner);
+    finish_results(&mut results, config);
+    Ok((results, spotted))
+}
+
+fn tag_interfaces(results: &mut [WifiData], scanner: &impl Scanner) {
+    for item in results {
+        item.interface = scanner.interface_of(&item.mac);
+    }
+}
+
+// Scan-wide totals written next to the numbered records under "summary"
+#[derive(Serialize, JsonSchema)]
+pub struct Summary {
+    pub total_networks: usize,
+    pub by_band: BTreeMap<String, usize>,
+    pub by_security: BTreeMap<String, usize>,
+    // Genuinely unencrypted networks; Enhanced Open (OWE) ones are excluded
+    pub open_networks: usize,
+    pub randomized_macs: usize,
+    // Registry matches only, keyed by `vendor` so custom labels don't split a vendor; randomized MACs are counted
+    // in `randomized_macs` instead
+    pub manufacturer_counts: BTreeMap<String, usize>,
+    pub anomalies: Vec<Anomaly>,
+    pub scan_started: DateTime<Utc>,
+    pub scan_finished: DateTime<Utc>,
+    // Backend scan() calls behind these results, see `Summary::with_scan_samples`
+    pub scan_calls: usize,
+    pub scan_latency_ms: Option<Spread>,
+    pub networks_per_scan: Option<Spread>,
+}
+
+// An SSID broadcast by several BSSIDs that don't agree with each other, the usual evil-twin signature
+#[derive(Serialize, JsonSchema)]
+pub struct Anomaly {
+    pub ssid: String,
+    // "security_mismatch" and/or "vendor_mismatch"
+    pub reasons: Vec<&'static str>,
+    pub bssids: Vec<String>,
+    pub security: Vec<String>,
+    pub manufacturers: Vec<String>,
+}
+
+// Groups records by SSID and flags groups whose security or vendor differs. Hidden networks are
+// skipped since they don't really share a name.
+fn find_anomalies(wifi_data: &[WifiData]) -> Vec<Anomaly> {
+    let mut by_ssid: BTreeMap<&str, Vec<&WifiData>> = BTreeMap::new();
+    for item in wifi_data.iter().filter(|item| !item.hidden) {
+        by_ssid.entry(item.ssid.as_str()).or_default().push(item);
+    }
+    let mut anomalies = Vec::new();
+    for (ssid, group) in by_ssid {
+        let bssids: BTreeSet<&str> = group.iter().map(|item| item.mac.as_str()).collect();
+        if bssids.len() < 2 {
+            continue;
+        }
+        let security: BTreeSet<&str> = group.iter().map(|item| item.security.as_str()).collect();
+        let manufacturers: BTreeSet<&str> = group.iter()
+            .filter_map(|item| item.vendor.as_deref().or(item.manufacturer.as_deref()))
+            .collect();
+        let mut reasons = Vec::new();
+        if security.len() > 1 {
+            reasons.push("security_mismatch");
+        }
+        if manufacturers.len() > 1 {
+            reasons.push("vendor_mismatch");
+        }
+        if reasons.is_empty() {
+            continue;
+        }
+        anomalies.push(Anomaly {
+            ssid: ssid.to_string(),
+            reasons,
+            bssids: bssids.into_iter().map(str::to_string).collect(),
+            security: security.into_iter().map(str::to_string).collect(),
+            manufacturers: manufacturers.into_iter().map(str::to_string).collect(),
+        });
+    }
+    anomalies
+}
+
+#[derive(Serialize, JsonSchema, Clone, Copy)]
+pub struct Spread {
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+}
+
+impl Spread {
+    fn of(values: impl Iterator<Item = f64>) -> Option<Spread> {
+        let values: Vec<f64> = values.collect();
+        if values.is_empty() {
+            return None;
+        }
+        Some(Spread {
+            min: values.iter().copied().fold(f64::INFINITY, f64::min),
+            max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            avg: values.iter().sum::<f64>() / values.len() as f64,
+        })
+    }
+}
+
+impl Summary {
+    pub fn new(wifi_data: &[WifiData], scan_started: DateTime<Utc>, scan_finished: DateTime<Utc>) -> Self {
+        let mut by_band = BTreeMap::new();
+        let mut by_security = BTreeMap::new();
+        let mut manufacturer_counts = BTreeMap::new();
+        for item in wifi_data {
+            *by_band.entry(item.band.clone()).or_insert(0) += 1;
+            *by_security.entry(item.security.as_str().to_string()).or_insert(0) += 1;
+            if let Some(vendor) = item.vendor.as_ref().filter(|_| !item.randomized) {
+                *manufacturer_counts.entry(vendor.clone()).or_insert(0) += 1;
+            }
+        }
+        Summary {
+            total_networks: wifi_data.len(),
+            by_band,
+            by_security,
+            open_networks: wifi_data.iter().filter(|item| item.security == Security::Open).count(),
+            randomized_macs: wifi_data.iter().filter(|item| item.randomized).count(),
+            manufacturer_counts,
+            anomalies: find_anomalies(wifi_data),
+            scan_started,
+            scan_finished,
+            scan_calls: 0,
+            scan_latency_ms: None,
+            networks_per_scan: None,
+        }
+    }
+
+    // Fills in the timing fields from what a `TimedScanner` recorded
+    pub fn with_scan_samples(self, samples: &[ScanSample]) -> Self {
+        Summary {
+            scan_calls: samples.len(),
+            scan_latency_ms: Spread::of(samples.iter().map(|sample| sample.latency.as_secs_f64() * 1000.0)),
+            networks_per_scan: Spread::of(samples.iter().map(|sample| sample.networks as f64)),
+            ..self
+        }
+    }
+}
+
+// The non-overlapping 20 MHz channels of the 2.4 GHz band
+const NON_OVERLAPPING_24GHZ: [i32; 3] = [1, 6, 11];
+
+// How busy one channel is
+#[derive(Serialize, JsonSchema)]
+pub struct ChannelLoad {
+    pub aps: usize,
+    // APs on neighbouring 2.4 GHz channels close enough to share spectrum with this one
+    pub overlapping_aps: usize,
+    // A 2.4 GHz channel other than 1, 6 or 11, which interferes with two of them
+    pub off_grid: bool,
+}
+
+// AP counts per band and channel, and the quietest of the 2.4 GHz channels 1, 6 and 11
+#[derive(Serialize, JsonSchema)]
+pub struct ChannelCongestion {
+    // Keyed by band first, since 5 and 6 GHz share channel numbers such as 149
+    pub channels: BTreeMap<String, BTreeMap<i32, ChannelLoad>>,
+    pub recommended_channel: i32,
+}
+
+impl ChannelCongestion {
+    pub fn new(wifi_data: &[WifiData]) -> Self {
+        let mut aps: BTreeMap<(&'static str, i32), usize> = BTreeMap::new();
+        for item in wifi_data {
+            for &channel in &item.channels {
+                // Only the reported channel has a known frequency; ones merged in by dedupe go by number
+                let frequency_mhz = if channel == item.channel { item.frequency_mhz } else { None };
+                *aps.entry((band_for(channel, frequency_mhz), channel)).or_insert(0) += 1;
+            }
+        }
+        // 2.4 GHz channels are 5 MHz apart but 20 MHz wide, so anything within 4 channels overlaps
+        let overlapping = |band: &str, channel: i32| -> usize {
+            if band != "2.4GHz" {
+                return 0;
+            }
+            aps.iter()
+                .filter(|(&(other_band, other), _)| other_band == band && other != channel && (other - channel).abs() <= 4)
+                .map(|(_, count)| count)
+                .sum()
+        };
+        let mut channels: BTreeMap<String, BTreeMap<i32, ChannelLoad>> = BTreeMap::new();
+        for (&(band, channel), &count) in &aps {
+            let load = ChannelLoad {
+                aps: count,
+                overlapping_aps: overlapping(band, channel),
+                off_grid: band == "2.4GHz" && !NON_OVERLAPPING_24GHZ.contains(&channel),
+            };
+            channels.entry(band.to_string()).or_default().insert(channel, load);
+        }
+        let recommended_channel = NON_OVERLAPPING_24GHZ.into_iter()
+            .min_by_key(|&channel| aps.get(&("2.4GHz", channel)).copied().unwrap_or(0) + overlapping("2.4GHz", channel))
+            .unwrap_or(NON_OVERLAPPING_24GHZ[0]);
+        ChannelCongestion { channels, recommended_channel }
+    }
+}
+
+// The records in `shape` plus the "summary" and "channel_congestion" objects, the JSON document written for a
+// finished scan
+pub fn results_document(wifi_data: &[WifiData], summary: &Summary, shape: OutputShape) -> Result<serde_json::Map<String, serde_json::Value>, ScanError> {
+    let mut document = match shape {
+        OutputShape::Map => number_results(wifi_data)?,
+        OutputShape::Array => {
+            let records = serde_json::to_value(wifi_data).map_err(ScanError::Serialize)?;
+            serde_json::Map::from_iter([("results".to_string(), records)])
+        }
+    };
+    document.insert("summary".to_string(), serde_json::to_value(summary).map_err(ScanError::Serialize)?);
+    let congestion = ChannelCongestion::new(wifi_data);
+    document.insert("channel_congestion".to_string(), serde_json::to_value(congestion).map_err(ScanError::Serialize)?);
+    Ok(document)
+}
+
+// Shape of `results_document` without the numbered keys, so the schema has something to derive from
+#[derive(JsonSchema)]
+#[allow(dead_code)]
+struct ResultsSections<'a> {
+    summary: Summary,
+    channel_congestion: ChannelCongestion,
+    // Only present with output_shape "array"; the default "map" shape numbers the records instead
+    results: Option<Vec<WifiData>>,
+    // Only present with --compare
+    delta: Option<ScanDelta<'a>>,
+}
+
+// JSON Schema of the results document: WifiData records, numbered or in a "results" array, next to the
+// summary sections
+pub fn results_schema() -> schemars::schema::RootSchema {
+    let mut schema = schemars::schema_for!(ResultsSections);
+    schema.schema.metadata().title = Some("wifi_module results".to_string());
+    let mut generator = schemars::gen::SchemaGenerator::default();
+    let record = generator.subschema_for::<WifiData>();
+    schema.definitions.extend(generator.take_definitions());
+    let object = schema.schema.object();
+    object.pattern_properties.insert("^[0-9]+$".to_string(), record);
+    schema
+}
+
+// Serializes a results document the way `config.pretty` asks for
+pub fn to_json<T: Serialize>(document: &T, pretty: bool) -> Result<String, ScanError> {
+    if pretty {
+        serde_json::to_string_pretty(document).map_err(ScanError::Serialize)
+    } else {
+        serde_json::to_string(document).map_err(ScanError::Serialize)
+    }
+}
+
+// Keys each record by its 1-based position, the shape written to the output files
+pub fn number_results(wifi_data: &[WifiData]) -> Result<serde_json::Map<String, serde_json::Value>, ScanError> {
+    let mut formatted_wifi_data = serde_json::Map::new();
+    for (i, data) in wifi_data.iter().enumerate() {
+        let key = (i + 1).to_string();
+        formatted_wifi_data.insert(key, serde_json::to_value(data).map_err(ScanError::Serialize)?);
+    }
+    Ok(formatted_wifi_data)
+}
+
+// Records of a JSON results document in either `OutputShape`, in their original order; the summary sections
+// are skipped
+pub fn read_results(filename: &str) -> Result<Vec<WifiData>, ScanError> {
+    let mut contents = String::new();
+    let file = File::open(filename).map_err(ScanError::ResultsRead)?;
+    if is_gzip(filename) {
+        flate2::read::MultiGzDecoder::new(file).read_to_string(&mut contents)
+    } else {
+        io::BufReader::new(file).read_to_string(&mut contents)
+    }.map_err(ScanError::ResultsRead)?;
+    let mut document: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&contents).map_err(ScanError::ResultsParse)?;
+    if let Some(records) = document.remove("results") {
+        return serde_json::from_value(records).map_err(ScanError::ResultsParse);
+    }
+    let mut numbered: Vec<(usize, serde_json::Value)> = document.into_iter()
+        .filter_map(|(key, value)| key.parse().ok().map(|position| (position, value)))
+        .collect();
+    numbered.sort_by_key(|(position, _)| *position);
+    numbered.into_iter()
+        .map(|(_, value)| serde_json::from_value(value).map_err(ScanError::ResultsParse))
+        .collect()
+}
+
+// A network present in both scans whose `fields` differ
+#[derive(Serialize, JsonSchema)]
+pub struct ChangedNetwork<'a> {
+    pub mac: &'a str,
+    pub fields: Vec<&'static str>,
+    pub previous: &'a WifiData,
+    pub current: &'a WifiData,
+}
+
+// What happened between two scans, matched by MAC
+#[derive(Serialize, JsonSchema)]
+pub struct ScanDelta<'a> {
+    pub added: Vec<&'a WifiData>,
+    pub removed: Vec<&'a WifiData>,
+    pub changed: Vec<ChangedNetwork<'a>>,
+}
+
+impl<'a> ScanDelta<'a> {
+    pub fn new(previous: &'a [WifiData], current: &'a [WifiData]) -> Self {
+        let previous_by_mac: HashMap<&str, &WifiData> = previous.iter().map(|item| (item.mac.as_str(), item)).collect();
+        let current_macs: BTreeSet<&str> = current.iter().map(|item| item.mac.as_str()).collect();
+        let mut delta = ScanDelta {
+            added: Vec::new(),
+            removed: previous.iter().filter(|item| !current_macs.contains(item.mac.as_str())).collect(),
+            changed: Vec::new(),
+        };
+        for item in current {
+            let Some(before) = previous_by_mac.get(item.mac.as_str()) else {
+                delta.added.push(item);
+                continue;
+            };
+            let fields: Vec<&'static str> = [
+                ("ssid", before.ssid != item.ssid),
+                ("channel", before.channel != item.channel),
+                ("band", before.band != item.band),
+                ("security", before.security != item.security),
+            ]
+            .into_iter()
+            .filter_map(|(field, differs)| differs.then_some(field))
+            .collect();
+            if !fields.is_empty() {
+                delta.changed.push(ChangedNetwork { mac: &item.mac, fields, previous: before, current: item });
+            }
+        }
+        delta
+    }
+}
+
+// Drops vendors the manufacturer filters reject, hashes MACs for `hash_mac` and applies `max_results`,
+// dropping the weakest networks first, puts the rest in `sort_by` order and last applies `signal_bucketing`
+fn finish_results(wifi_data: &mut Vec<WifiData>, config: &Config) {
+    wifi_data.retain(|item| config.keeps_manufacturer(item));
+    if let (true, Some(salt)) = (config.hash_mac, &config.mac_salt) {
+        wifi_data.iter_mut().for_each(|item| item.mac = hashed_mac(&item.mac, salt));
+    }
+    if let Some(max_results) = config.max_results {
+        sort_results(wifi_data, SortBy::Signal);
+        wifi_data.truncate(max_results);
+    }
+    sort_results(wifi_data, config.sort_by);
+    wifi_data.iter_mut().for_each(|item| config.signal_bucketing.apply(item));
+}
+
+fn sort_results(wifi_data: &mut [WifiData], by: SortBy) {
+    wifi_data.sort_by(|a, b| {
+        let primary = match by {
+            SortBy::Mac => std::cmp::Ordering::Equal,
+            SortBy::Ssid => a.ssid.cmp(&b.ssid),
+            // None sorts below Some, so reversing puts unknown signals last
+            SortBy::Signal => b.signal_dbm.cmp(&a.signal_dbm),
+            SortBy::Channel => a.channel.cmp(&b.channel),
+        };
+        primary.then_with(|| a.mac.cmp(&b.mac))
+    });
+}
+
+// Keeps the first record of each group and merges the other channels into it; hidden SSIDs never group together
+fn dedupe(wifi_data: Vec<WifiData>, by: DedupeBy) -> Vec<WifiData> {
+    if by == DedupeBy::None {
+        return wifi_data;
+    }
+    let mut deduped: Vec<WifiData> = Vec::new();
+    let mut index_by_key: HashMap<String, usize> = HashMap::new();
+    for item in wifi_data {
+        let key = match by {
+            DedupeBy::Ssid if !item.hidden => item.ssid.clone(),
+            _ => item.mac.clone(),
+        };
+        match index_by_key.get(&key) {
+            Some(&index) => {
+                let kept = &mut deduped[index];
+                for channel in item.channels {
+                    if !kept.channels.contains(&channel) {
+                        kept.channels.push(channel);
+                    }
+                }
+                kept.channels.sort_unstable();
+            }
+            None => {
+                index_by_key.insert(key, deduped.len());
+                deduped.push(item);
+            }
+        }
+    }
+    deduped
+}
+
+// Enriches raw scan records with OUI, band and security details, dropping networks the config filters out
+pub fn convert_to_wifi_data(networks: &[tokio_wifiscanner::Wifi], oui_data: &OuiDatabase, config: &Config) -> Vec<WifiData> {
+    networks.iter()
+        .filter(|network| config.keeps(network))
+        .map(|network| WifiData::from_scan(network, oui_data, config.sanitize))
+        .collect()
+}
+
+// Same result as `convert_to_wifi_data`, with lookups spread over the rayon pool. Each record costs under a
+// microsecond serially, so this only pays off for large batches; `cargo bench --features parallel` prints
+// the crossover for the machine it runs on (it scales with core count).
+#[cfg(feature = "parallel")]
+pub fn convert_to_wifi_data_parallel(networks: &[tokio_wifiscanner::Wifi], oui_data: &OuiDatabase, config: &Config) -> Vec<WifiData> {
+    use rayon::prelude::*;
+    networks.par_iter()
+        .filter(|network| config.keeps(network))
+        .map(|network| WifiData::from_scan(network, oui_data, config.sanitize))
+        .collect()
+}
+
+impl WifiData {
+    // The one mapping from a raw scan record to an output record: OUI and label lookup, security, channel
+    // and band, and `sanitize`. Fields only a scheduled scan or a later step knows are left unset.
+    pub fn from_scan(network: &tokio_wifiscanner::Wifi, oui_data: &OuiDatabase, sanitize: SanitizeMode) -> WifiData {
+        let mac = normalize_mac(&network.mac);
+        let randomized = is_locally_administered(&mac);
+        let label = oui_data.label(&mac).map(|label| sanitize.apply(label));
+        let registry_name = oui_data.registry_name(&mac).map(|name| sanitize.apply(name));
+        let manufacturer_resolved = registry_name.is_some();
+        let vendor = registry_name.as_deref().map(normalize_vendor);
+        let manufacturer = match label.clone().or(registry_name) {
+            Some(name) => name,
+            None if randomized => sanitize.apply(RANDOMIZED_MANUFACTURER),
+            None => sanitize.apply(UNKNOWN_MANUFACTURER),
+        };
+        let network_security = security_label(&network.security);
+        let ssid_raw = ssid_bytes(&network.ssid);
+        let (ssid_sanitized, hidden) = ssid_for_output(&String::from_utf8_lossy(&ssid_raw), sanitize);
+        let (channel, frequency_mhz) = channel_and_frequency(&network.channel);
+        WifiData {
+            ssid: ssid_sanitized,
+            ssid_hex: ssid_hex(&ssid_raw),
+            hidden,
+            mac,
+            manufacturer: Some(manufacturer),
+            manufacturer_resolved,
+            vendor,
+            label,
+            network_security: network_security.to_string(),
+            security: Security::parse(&network.security),
+            security_raw: network.security.clone(),
+            channel,
+            channels: vec![channel],
+            channels_seen: vec![channel],
+            band: band_for(channel, frequency_mhz).to_string(),
+            frequency_mhz,
+            signal_dbm: parse_signal_dbm(&network.signal_level),
+            signal_min: None,
+            signal_max: None,
+            signal_avg: None,
+            signal_bucket: None,
+            randomized,
+            wifi_durations: String::new(),
+            first_seen: None,
+            total_dwell_secs: None,
+            sighting_count: None,
+            stability: None,
+            location: None,
+            interface: None,
+            last_seen: None,
+        }
+    }
+}
+
+fn security_label(security: &str) -> &'static str {
+    if security.is_empty() { "Open" } else { "Secured" }
+}
+
+// Channel numbers alone are ambiguous across bands, so the lower bands win: 6 GHz channels (every fourth
+// from 1 to 233) are only reported as 6GHz where no 2.4 or 5 GHz channel has the number, i.e. 17-29 and
+// 181-233. `band_for` uses the frequency instead when there is one.
+pub fn channel_to_band(channel: i32) -> &'static str {
+    match channel {
+        1..=14 => "2.4GHz",
+        32..=177 => "5GHz",
+        1..=233 if channel % 4 == 1 => "6GHz",
+        _ => "Unknown",
+    }
+}
+
+// Centre frequency of a channel, resolving overlapping numbers the same way as `channel_to_band`
+pub fn channel_to_frequency(channel: i32) -> Option<u32> {
+    let number = u32::try_from(channel).ok()?;
+    match channel_to_band(channel) {
+        "2.4GHz" if number == 14 => Some(2484),
+        "2.4GHz" => Some(2407 + number * 5),
+        "5GHz" => Some(5000 + number * 5),
+        "6GHz" => Some(5950 + number * 5),
+        _ => None,
+    }
+}
+
+pub fn frequency_to_channel(mhz: u32) -> Option<u32> {
+    match mhz {
+        2484 => Some(14),
+        2412..=2472 => Some((mhz - 2407) / 5),
+        5955..=7115 => Some((mhz - 5950) / 5),
+        5000..=5895 => Some((mhz - 5000) / 5),
+        _ => None,
+    }
+}
+
+// The frequency settles which band an overlapping channel number belongs to
+fn band_for(channel: i32, frequency_mhz: Option<u32>) -> &'static str {
+    match frequency_mhz {
+        Some(2400..=2500) => "2.4GHz",
+        Some(5000..=5900) => "5GHz",
+        Some(5925..=7125) => "6GHz",
+        _ => channel_to_band(channel),
+    }
+}
+
+// Some backends put the frequency in the channel field ("5180"); either way both are filled in when known
+fn channel_and_frequency(raw: &str) -> (i32, Option<u32>) {
+    let channel: i32 = raw.trim().parse().unwrap_or(0);
+    match u32::try_from(channel).ok().and_then(frequency_to_channel) {
+        Some(from_frequency) => (from_frequency as i32, Some(channel as u32)),
+        None => (channel, channel_to_frequency(channel)),
+    }
+}
+
+fn format_absolute_interval(start: &Sighting, end: &Sighting) -> String {
+    format!(
+        "{}/{}",
+        start.timestamp.to_rfc3339_opts(SecondsFormat::Secs, true),
+        end.timestamp.to_rfc3339_opts(SecondsFormat::Secs, true)
+    )
+}
+
+// Backends report the level as text, e.g. "-67" or "-67.00" from `iw`
+fn parse_signal_dbm(signal_level: &str) -> Option<i32> {
+    let trimmed = signal_level.trim();
+    trimmed.parse::<i32>().ok()
+        .or_else(|| trimmed.parse::<f64>().ok().map(|dbm| dbm.round() as i32))
+}
+
+// None when no sample for the device ever parsed
+fn signal_average(samples: &[i32]) -> Option<f64> {
+    if samples.is_empty() {
+        return None;
+    }
+    Some(samples.iter().map(|&dbm| dbm as f64).sum::<f64>() / samples.len() as f64)
+}
+
+const HIDDEN_SSID: &str = "<hidden>";
+
+// The SSID bytes behind iw's output, which escapes unprintable and non-ASCII bytes as "\xNN".
+// Anything that isn't a complete escape is kept as is.
+fn ssid_bytes(ssid: &str) -> Vec<u8> {
+    let raw = ssid.as_bytes();
+    let mut bytes = Vec::with_capacity(raw.len());
+    let mut i = 0;
+    while i < raw.len() {
+        let escape = match raw[i..] {
+            [b'\\', b'x', hi, lo, ..] => (hi as char).to_digit(16).zip((lo as char).to_digit(16)),
+            _ => None,
+        };
+        match escape {
+            Some((hi, lo)) => {
+                bytes.push((hi * 16 + lo) as u8);
+                i += 4;
+            }
+            None => {
+                bytes.push(raw[i]);
+                i += 1;
+            }
+        }
+    }
+    bytes
+}
+
+// Best-effort text of an SSID as iw prints it
+fn ssid_text(ssid: &str) -> String {
+    String::from_utf8_lossy(&ssid_bytes(ssid)).into_owned()
+}
+
+// Hex of the raw bytes when they don't read back as printable text, so anomalous SSIDs aren't lost to
+// sanitizing or U+FFFD
+fn ssid_hex(raw: &[u8]) -> Option<String> {
+    let printable = std::str::from_utf8(raw).is_ok_and(|text| !text.chars().any(char::is_control));
+    (!printable).then(|| raw.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+// Sanitized SSID plus whether the network hides it; hidden networks get a placeholder instead of ""
+fn ssid_for_output(ssid: &str, sanitize: SanitizeMode) -> (String, bool) {
+    let sanitized = sanitize.apply(ssid);
+    if sanitized.trim().is_empty() {
+        (HIDDEN_SSID.to_string(), true)
+    } else {
+        (sanitized, false)
+    }
+}
+
+// Legal-form words dropped from the end of an organization name
+const CORPORATE_SUFFIXES: [&str; 14] = [
+    "INC", "INCORPORATED", "CORP", "CORPORATION", "CO", "COMPANY", "LTD", "LIMITED", "LLC", "GMBH", "AG", "SA", "BV", "PLC",
+];
+
+// Registry spellings of the same vendor, keyed by the suffix-free upper-case name
+const VENDOR_ALIASES: [(&str, &str); 12] = [
+    ("APPLE", "Apple"),
+    ("SAMSUNG ELECTRONICS", "Samsung"),
+    ("SAMSUNG ELECTRO-MECHANICS", "Samsung"),
+    ("CISCO SYSTEMS", "Cisco"),
+    ("CISCO MERAKI", "Cisco"),
+    ("HUAWEI TECHNOLOGIES", "Huawei"),
+    ("HUAWEI DEVICE", "Huawei"),
+    ("INTEL CORPORATE", "Intel"),
+    ("TP-LINK TECHNOLOGIES", "TP-Link"),
+    ("TP-LINK CORPORATION", "TP-Link"),
+    ("GOOGLE", "Google"),
+    ("AMAZON TECHNOLOGIES", "Amazon"),
+];
+
+// "Apple, Inc.", "Apple Inc" and "APPLE INC" all become "Apple": whitespace is collapsed, a trailing legal
+// form is dropped and known variants map to one name through `VENDOR_ALIASES`. Other names keep their case.
+pub fn normalize_vendor(name: &str) -> String {
+    // Commas split words too, so "Co.,Ltd" is two suffixes
+    let mut words: Vec<&str> = name.split(|c: char| c.is_whitespace() || c == ',').filter(|word| !word.is_empty()).collect();
+    while let Some(last) = words.last() {
+        let bare = last.trim_matches('.').to_uppercase();
+        if words.len() > 1 && CORPORATE_SUFFIXES.contains(&bare.as_str()) {
+            words.pop();
+        } else {
+            break;
+        }
+    }
+    let collapsed = words.join(" ");
+    let trimmed = collapsed.trim_end_matches('.');
+    let key = trimmed.to_uppercase();
+    VENDOR_ALIASES.iter()
+        .find(|(alias, _)| *alias == key)
+        .map(|(_, canonical)| canonical.to_string())
+        .unwrap_or_else(|| trimmed.to_string())
+}
+
+const RANDOMIZED_MANUFACTURER: &str = "Randomized (locally administered)";
+// Placeholder for a MAC whose prefix isn't in the OUI registry
+const UNKNOWN_MANUFACTURER: &str = "Unknown";
+
+// Randomized MACs set the locally administered bit (0x02) of the first octet and never resolve in the OUI table
+pub fn is_locally_administered(mac: &str) -> bool {
+    mac.get(..2)
+        .and_then(|octet| u8::from_str_radix(octet, 16).ok())
+        .is_some_and(|octet| octet & 0x02 != 0)
+}
+
+// Canonical "AA:BB:CC:DD:EE:FF" form of a colon, dash or Cisco dot separated address.
+// Anything that isn't 12 hex digits once the separators are gone is only trimmed and uppercased.
+pub fn normalize_mac(raw: &str) -> String {
+    let hex: String = raw.trim().chars().filter(|c| !matches!(c, ':' | '-' | '.')).collect::<String>().to_uppercase();
+    if hex.len() != 12 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return raw.trim().to_uppercase();
+    }
+    (0..12).step_by(2).map(|i| &hex[i..i + 2]).collect::<Vec<_>>().join(":")
+}
+
+// First 16 hex digits of SHA-256 over `salt` and the normalized MAC, what `hash_mac` reports in place of
+// the address. Handy for finding a known device in hashed results.
+pub fn hashed_mac(mac: &str, salt: &str) -> String {
+    use sha2::{Digest, Sha256};
+    let digest = Sha256::new().chain_update(salt).chain_update(normalize_mac(mac)).finalize();
+    digest[..8].iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Database path of a "sqlite://path.db" output target
+pub fn sqlite_path(output: &str) -> Option<&str> {
+    output.strip_prefix("sqlite://")
+}
+
+// Output target that writes to stdout instead of a file, for piping into jq and the like
+pub const STDOUT_TARGET: &str = "-";
+
+// Output files with this suffix are gzip-compressed
+pub const GZIP_SUFFIX: &str = ".gz";
+
+fn is_gzip(filename: &str) -> bool {
+    filename.ends_with(GZIP_SUFFIX)
+}
+
+// `filename` with ".gz" added when `compress` is set; stdout, database targets and names that
+// already end in ".gz" are left as they are
+pub fn compressed_name(filename: &str, compress: bool) -> String {
+    if !compress || is_gzip(filename) || filename == STDOUT_TARGET || sqlite_path(filename).is_some() {
+        return filename.to_string();
+    }
+    format!("{}{}", filename, GZIP_SUFFIX)
+}
+
+// A results file, gzip-compressed when its name ends in ".gz". Appending to a compressed file adds
+// another gzip member, which gzip and zcat read back as one stream.
+pub enum OutputFile {
+    Plain(io::BufWriter<File>),
+    Gzip(flate2::write::GzEncoder<io::BufWriter<File>>),
+}
+
+impl OutputFile {
+    fn new(filename: &str, file: File) -> Self {
+        let writer = io::BufWriter::new(file);
+        if is_gzip(filename) {
+            OutputFile::Gzip(flate2::write::GzEncoder::new(writer, flate2::Compression::default()))
+        } else {
+            OutputFile::Plain(writer)
+        }
+    }
+
+    // Writes the gzip trailer and flushes. Dropping the file does the same but swallows errors.
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            OutputFile::Plain(mut writer) => writer.flush(),
+            OutputFile::Gzip(encoder) => encoder.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            OutputFile::Plain(writer) => writer.write(buf),
+            OutputFile::Gzip(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            OutputFile::Plain(writer) => writer.flush(),
+            OutputFile::Gzip(encoder) => encoder.flush(),
+        }
+    }
+}
+
+// Truncates `filename`, or adds to the end of it when `append` is set
+fn open_output(filename: &str, append: bool) -> io::Result<File> {
+    if append {
+        OpenOptions::new().create(true).append(true).open(filename)
+    } else {
+        File::create(filename)
+    }
+}
+
+pub fn create_output(filename: &str, append: bool) -> io::Result<OutputFile> {
+    Ok(OutputFile::new(filename, open_output(filename, append)?))
+}
+
+pub fn write_json_to_file(json_data: &str, filename: &str) -> Result<(), ScanError> {
+    if filename == STDOUT_TARGET {
+        return writeln!(io::stdout().lock(), "{}", json_data).map_err(ScanError::Output);
+    }
+    let mut output = create_output(filename, false).map_err(ScanError::Output)?;
+    output.write_all(json_data.as_bytes()).map_err(ScanError::Output)?;
+    output.finish().map_err(ScanError::Output)
+}
+
+pub fn write_ndjson_to_file(data: &[WifiData], filename: &str, append: bool) -> Result<(), ScanError> {
+    if filename == STDOUT_TARGET {
+        return write_ndjson(data, &mut io::stdout().lock());
+    }
+    let mut output = create_output(filename, append).map_err(ScanError::Output)?;
+    write_ndjson(data, &mut output)?;
+    output.finish().map_err(ScanError::Output)
+}
+
+// Writes each record as its own line and flushes, so a streaming consumer sees whole batches
+pub fn write_ndjson<W: Write>(data: &[WifiData], writer: &mut W) -> Result<(), ScanError> {
+    for item in data {
+        let line = serde_json::to_string(item).map_err(ScanError::Serialize)?;
+        writeln!(writer, "{}", line).map_err(ScanError::Output)?;
+    }
+    writer.flush().map_err(ScanError::Output)
+}
+
+// When appending, the header is only written if the file starts out empty
+pub fn write_csv_to_file(data: &[WifiData], filename: &str, append: bool) -> Result<(), ScanError> {
+    if filename == STDOUT_TARGET {
+        return write_csv(data, io::stdout().lock(), true);
+    }
+    let file = open_output(filename, append).map_err(ScanError::Output)?;
+    let needs_header = file.metadata().map_err(ScanError::Output)?.len() == 0;
+    let mut output = OutputFile::new(filename, file);
+    write_csv(data, &mut output, needs_header)?;
+    output.finish().map_err(ScanError::Output)
+}
+
+fn write_csv<W: Write>(data: &[WifiData], writer: W, needs_header: bool) -> Result<(), ScanError> {
+    let mut wtr = csv::Writer::from_writer(writer);
+    if needs_header {
+        wtr.write_record(["ssid", "mac", "manufacturer", "network_security", "channel", "signal", "durations"])
+            .map_err(|e| ScanError::Output(e.into()))?;
+    }
+    for item in data {
+        wtr.write_record([
+            item.ssid.as_str(),
+            item.mac.as_str(),
+            item.manufacturer.as_deref().unwrap_or(""),
+            item.network_security.as_str(),
+            &item.channel.to_string(),
+            &item.signal_dbm.map(|dbm| dbm.to_string()).or_else(|| item.signal_bucket.clone()).unwrap_or_default(),
+            // Quoted by the writer since the interval list is comma separated
+            item.wifi_durations.as_str(),
+        ]).map_err(|e| ScanError::Output(e.into()))?;
+    }
+    wtr.flush().map_err(ScanError::Output)?;
+    Ok(())
+}
+
+// Wigle's auth mode notation for each security level
+fn wigle_auth_mode(security: Security) -> &'static str {
+    match security {
+        Security::Open => "[ESS]",
+        Security::Owe => "[RSN-OWE-CCMP][ESS]",
+        Security::Wep => "[WEP][ESS]",
+        Security::Wpa => "[WPA-PSK-TKIP][ESS]",
+        Security::Wpa2 => "[WPA2-PSK-CCMP][ESS]",
+        Security::Wpa3 => "[WPA3-SAE-CCMP][ESS]",
+        Security::Wpa2Wpa3Mixed => "[WPA2-PSK-CCMP][WPA3-SAE-CCMP][ESS]",
+        Security::Unknown => "",
+    }
+}
+
+// Records without a first sighting (instant mode) are dated to when the file is written.
+// When appending, the two header lines are only written if the file starts out empty.
+pub fn write_wigle_to_file(data: &[WifiData], filename: &str, append: bool) -> Result<(), ScanError> {
+    if filename == STDOUT_TARGET {
+        return write_wigle(data, io::stdout().lock(), true);
+    }
+    let file = open_output(filename, append).map_err(ScanError::Output)?;
+    let needs_header = file.metadata().map_err(ScanError::Output)?.len() == 0;
+    let mut output = OutputFile::new(filename, file);
+    write_wigle(data, &mut output, needs_header)?;
+    output.finish().map_err(ScanError::Output)
+}
+
+fn write_wigle<W: Write>(data: &[WifiData], mut writer: W, needs_header: bool) -> Result<(), ScanError> {
+    if needs_header {
+        writeln!(writer, "WigleWifi-1.4,appRelease={},model=,release=,device=wifi_module,display=,board=,brand=", env!("CARGO_PKG_VERSION"))
+            .map_err(ScanError::Output)?;
+    }
+    let mut wtr = csv::WriterBuilder::new().has_headers(false).from_writer(writer);
+    if needs_header {
+        wtr.write_record([
+            "MAC", "SSID", "AuthMode", "FirstSeen", "Channel", "RSSI",
+            "CurrentLatitude", "CurrentLongitude", "AltitudeMeters", "AccuracyMeters", "Type",
+        ]).map_err(|e| ScanError::Output(e.into()))?;
+    }
+    let written = Utc::now();
+    let coordinate = |value: Option<f64>| value.map(|value| value.to_string()).unwrap_or_default();
+    for item in data {
+        let location = item.location.as_ref();
+        wtr.write_record([
+            item.mac.as_str(),
+            if item.hidden { "" } else { item.ssid.as_str() },
+            wigle_auth_mode(item.security),
+            &item.first_seen.unwrap_or(written).format("%Y-%m-%d %H:%M:%S").to_string(),
+            &item.channel.to_string(),
+            &item.signal_dbm.map(|dbm| dbm.to_string()).unwrap_or_default(),
+            &coordinate(location.map(|location| location.latitude)),
+            &coordinate(location.map(|location| location.longitude)),
+            &coordinate(location.and_then(|location| location.altitude)),
+            "",
+            "WIFI",
+        ]).map_err(|e| ScanError::Output(e.into()))?;
+    }
+    wtr.flush().map_err(ScanError::Output)?;
+    Ok(())
+}
+
+// Records without a location can't be placed and are left out with a warning
+pub fn write_kml_to_file(data: &[WifiData], filename: &str) -> Result<(), ScanError> {
+    if filename == STDOUT_TARGET {
+        return write_kml(data, io::stdout().lock());
+    }
+    let mut output = create_output(filename, false).map_err(ScanError::Output)?;
+    write_kml(data, &mut output)?;
+    output.finish().map_err(ScanError::Output)
+}
+
+fn write_kml<W: Write>(data: &[WifiData], mut writer: W) -> Result<(), ScanError> {
+    let unlocated = data.iter().filter(|item| item.location.is_none()).count();
+    if unlocated > 0 {
+        warn!("Skipping {} networks without a location in the KML output", unlocated);
+    }
+    let mut kml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<kml xmlns=\"http://www.opengis.net/kml/2.2\">\n<Document>\n");
+    for item in data {
+        let Some(location) = item.location else {
+            continue;
+        };
+        let description = format!(
+            "MAC: {}\nVendor: {}\nSecurity: {}\nChannel: {}",
+            item.mac,
+            item.manufacturer.as_deref().unwrap_or(UNKNOWN_MANUFACTURER),
+            item.security.as_str(),
+            item.channel
+        );
+        // KML orders coordinates longitude first
+        let coordinates = match location.altitude {
+            Some(altitude) => format!("{},{},{}", location.longitude, location.latitude, altitude),
+            None => format!("{},{}", location.longitude, location.latitude),
+        };
+        kml.push_str(&format!(
+            "  <Placemark>\n    <name>{}</name>\n    <description>{}</description>\n    <Point><coordinates>{}</coordinates></Point>\n  </Placemark>\n",
+            xml_escape(&item.ssid),
+            xml_escape(&description),
+            coordinates
+        ));
+    }
+    kml.push_str("</Document>\n</kml>\n");
+    writer.write_all(kml.as_bytes()).map_err(ScanError::Output)?;
+    writer.flush().map_err(ScanError::Output)
+}
+
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Source of raw scan results, so the scan loops can run against scripted data in tests
+pub trait Scanner {
+    fn scan(&self) -> impl Future<Output = Result<Vec<tokio_wifiscanner::Wifi>, ScanError>> + Send;
+
+    // Interface a normalized MAC was last seen on, for backends that scan named interfaces
+    fn interface_of(&self, _mac: &str) -> Option<String> {
+        None
+    }
+}
+
+// The platform scanner: tokio_wifiscanner on its default interface, or `iw` on the named ones. Several
+// interfaces are scanned concurrently and their results merged by BSSID, keeping the strongest reading.
+#[derive(Default)]
+pub struct SystemScanner {
+    interfaces: Vec<String>,
+    seen_on: Mutex<HashMap<String, String>>,
+    hop_frequencies: Vec<u32>,
+    next_hop: AtomicUsize,
+}
+
+impl SystemScanner {
+    pub fn new(interfaces: Vec<String>) -> Self {
+        SystemScanner { interfaces, ..SystemScanner::default() }
+    }
+
+    // Each scan() then tunes the named interfaces to the next of `channels` in turn, so a scheduled or
+    // monitor run covers all of them. Channels without a known frequency are skipped.
+    pub fn with_channel_hopping(mut self, channels: &[i32]) -> Self {
+        self.hop_frequencies = channels.iter().filter_map(|&channel| channel_to_frequency(channel)).collect();
+        self
+    }
+}
+
+impl Scanner for SystemScanner {
+    async fn scan(&self) -> Result<Vec<tokio_wifiscanner::Wifi>, ScanError> {
+        if self.interfaces.is_empty() {
+            return tokio_wifiscanner::scan().await.map_err(ScanError::Scan);
+        }
+        let hop = (!self.hop_frequencies.is_empty()).then(|| {
+            let index = self.next_hop.fetch_add(1, Ordering::Relaxed) % self.hop_frequencies.len();
+            self.hop_frequencies[index]
+        });
+        if let Some(freq) = hop {
+            debug!("Scanning {} MHz", freq);
+        }
+        let mut strongest: HashMap<String, (String, tokio_wifiscanner::Wifi)> = HashMap::new();
+        for (interface, network) in interface::scan_interfaces(&self.interfaces, hop).await? {
+            let mac = normalize_mac(&network.mac);
+            let stronger = strongest.get(&mac).is_none_or(|(_, kept)| signal_rank(kept) < signal_rank(&network));
+            if stronger {
+                strongest.insert(mac, (interface, network));
+            }
+        }
+        let mut seen_on = self.seen_on.lock().unwrap();
+        Ok(strongest.into_iter()
+            .map(|(mac, (interface, network))| {
+                seen_on.insert(mac, interface);
+                network
+            })
+            .collect())
+    }
+
+    fn interface_of(&self, mac: &str) -> Option<String> {
+        self.seen_on.lock().unwrap().get(mac).cloned()
+    }
+}
+
+// How long one backend scan() call took and how many networks it returned (0 when it failed)
+#[derive(Clone, Copy)]
+pub struct ScanSample {
+    pub latency: std::time::Duration,
+    pub networks: usize,
+}
+
+// Wraps a scanner and records a `ScanSample` for every call that completes
+pub struct TimedScanner<S> {
+    inner: S,
+    samples: Mutex<Vec<ScanSample>>,
+}
+
+impl<S: Scanner> TimedScanner<S> {
+    pub fn new(inner: S) -> Self {
+        TimedScanner { inner, samples: Mutex::new(Vec::new()) }
+    }
+
+    // Returns the samples recorded since the last call
+    pub fn take_samples(&self) -> Vec<ScanSample> {
+        std::mem::take(&mut *self.samples.lock().unwrap())
+    }
+}
+
+impl<S: Scanner + Sync> Scanner for TimedScanner<S> {
+    async fn scan(&self) -> Result<Vec<tokio_wifiscanner::Wifi>, ScanError> {
+        let started = Instant::now();
+        let result = self.inner.scan().await;
+        let sample = ScanSample {
+            latency: started.elapsed(),
+            networks: result.as_ref().map_or(0, Vec::len),
+        };
+        self.samples.lock().unwrap().push(sample);
+        result
+    }
+
+    fn interface_of(&self, mac: &str) -> Option<String> {
+        self.inner.interface_of(mac)
+    }
+}
+
+// Retries transient backend failures and stalled attempts, doubling the wait from 500ms after each one
+async fn scan_with_retry(scanner: &impl Scanner, config: &Config) -> Result<Vec<tokio_wifiscanner::Wifi>, ScanError> {
+    let attempts = config.scan_retries;
+    let limit = tokio::time::Duration::from_secs(config.scan_timeout_secs);
+    let mut backoff = tokio::time::Duration::from_millis(500);
+    let mut attempt = 1;
+    loop {
+        let result = tokio::time::timeout(limit, scanner.scan()).await.unwrap_or(Err(ScanError::Timeout(limit)));
+        match result {
+            Ok(networks) => {
+                debug!("Scan returned {} networks", networks.len());
+                return Ok(networks);
+            }
+            Err(e) if attempt < attempts => {
+                warn!("Scan attempt {} of {} failed: {}, retrying in {}ms", attempt, attempts, e, backoff.as_millis());
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// Orders readings of the same BSSID, an unparseable signal counting as the weakest
+fn signal_rank(network: &tokio_wifiscanner::Wifi) -> i32 {
+    parse_signal_dbm(&network.signal_level).unwrap_or(i32::MIN)
+}
+
+// Runs `scan_count` back-to-back scans and keeps each BSSID's strongest reading, in the order they were
+// first seen. A failed pass is skipped as long as another one succeeds.
+async fn scan_passes(scanner: &impl Scanner, config: &Config) -> Result<Vec<tokio_wifiscanner::Wifi>, ScanError> {
+    if config.scan_count <= 1 {
+        return scan_with_retry(scanner, config).await;
+    }
+    let mut merged: Vec<tokio_wifiscanner::Wifi> = Vec::new();
+    let mut index_by_mac: HashMap<String, usize> = HashMap::new();
+    let mut last_error = None;
+    let mut any_succeeded = false;
+    for pass in 1..=config.scan_count {
+        match scan_with_retry(scanner, config).await {
+            Ok(networks) => {
+                any_succeeded = true;
+                for network in networks {
+                    match index_by_mac.get(&normalize_mac(&network.mac)) {
+                        Some(&index) if signal_rank(&merged[index]) < signal_rank(&network) => merged[index] = network,
+                        Some(_) => {}
+                        None => {
+                            index_by_mac.insert(normalize_mac(&network.mac), merged.len());
+                            merged.push(network);
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                warn!("Scan pass {} of {} failed: {}", pass, config.scan_count, e);
+                last_error = Some(e);
+            }
+        }
+    }
+    match last_error {
+        Some(e) if !any_succeeded => Err(e),
+        _ => {
+            debug!("{} scans found {} networks", config.scan_count, merged.len());
+            Ok(merged)
+        }
+    }
+}
+
+// Builds one record per device from the presence windows collected by the scheduled scan
+fn generate_results(tracks: &HashMap<String, DeviceTrack>, cycles: u64, oui_data: &OuiDatabase, config: &Config) -> Vec<WifiData> {
+    let mut results = Vec::new();
+    for track in tracks.values() {
+        let windows = track.windows(config.merge_gap_secs);
+        let total_dwell_secs = windows.iter().map(|(start, end)| end.instant.duration_since(start.instant).as_secs()).sum();
+        let durations = windows.iter()
+            .map(|(start, end)| {
+                if let TimestampFormat::Absolute = config.timestamp_format {
+                    return format_absolute_interval(start, end);
+                }
+                // Ensure intervals are formatted from lower to higher time
+                let start_secs = start.instant.elapsed().as_secs();
+                let end_secs = end.instant.elapsed().as_secs();
+                if start_secs <= end_secs {
+                    format!("{}-{}", start_secs, end_secs)
+                } else {
+                    format!("{}-{}", end_secs, start_secs)
+                }
+            })
+            .collect::<Vec<String>>().join(",");
+
+        // Enriched exactly like an instant record, then given what only a scheduled scan knows
+        let samples = &track.signal_samples;
+        results.push(WifiData {
+            signal_min: samples.iter().min().copied(),
+            signal_max: samples.iter().max().copied(),
+            signal_avg: signal_average(samples),
+            channels_seen: track.channels_seen.clone(),
+            wifi_durations: durations,
+            first_seen: Some(track.first_seen.timestamp),
+            last_seen: Some(track.last_seen.timestamp),
+            total_dwell_secs: Some(total_dwell_secs),
+            sighting_count: Some(track.sightings),
+            stability: (cycles > 0).then(|| track.sightings as f64 / cycles as f64),
+            ..WifiData::from_scan(&track.network, oui_data, config.sanitize)
+        });
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_keeps_quotes_and_unicode() {
+        let sanitized = SanitizeMode::ControlCharsOnly.apply("Joe's Café");
+        assert_eq!(sanitized, "Joe's Café");
+
+        let json = serde_json::to_string(&sanitized).unwrap();
+        let round_tripped: String = serde_json::from_str(&json).unwrap();
+        assert_eq!(round_tripped, "Joe's Café");
+    }
+
+    #[test]
+    fn merge_gap_compares_the_exact_gap() {
+        let network = tokio_wifiscanner::Wifi { mac: "00:1B:63:84:45:E6".to_string(), ..Default::default() };
+        let start = Sighting::now();
+        let at = |millis: u64| Sighting { instant: start.instant + tokio::time::Duration::from_millis(millis), ..start };
+        let mut track = DeviceTrack::new(network, start, 1);
+        // Away from 1s to 2.9s, then back until 4s
+        track.intervals.push((start, at(1_000)));
+        (track.window_start, track.last_seen) = (at(2_900), at(4_000));
+        assert_eq!(track.windows(1).len(), 2, "a 1.9s gap is more than 1s");
+        assert_eq!(track.windows(2).len(), 1, "a 1.9s gap is under 2s");
+
+        // A gap of exactly 2s isn't under 2s
+        track.window_start = at(3_000);
+        assert_eq!(track.windows(2).len(), 2);
+        assert_eq!(track.windows(0).len(), 2);
+    }
+
+    // One Apple MAC and one randomized MAC carry custom labels; `name` keeps the labels file apart per test
+    fn scan_with_labels(name: &str, macs: &[&str]) -> Vec<WifiData> {
+        let path = std::env::temp_dir().join(format!("wifi_module_{}_{}.csv", name, std::process::id()));
+        std::fs::write(&path, "00:1b:63:84:45:e6,Front desk\n02:00:00:00:00:01,Test phone\n").unwrap();
+        let csv = "Registry,Assignment,Organization Name,Organization Address\n\
+                   MA-L,001B63,\"Apple, Inc.\",1 Infinite Loop Cupertino CA US 95014\n";
+        let oui = OuiDatabase::from_reader(csv.as_bytes()).unwrap().with_custom_labels(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        macs.iter()
+            .map(|mac| tokio_wifiscanner::Wifi { mac: mac.to_string(), ..Default::default() })
+            .map(|network| WifiData::from_scan(&network, &oui, SanitizeMode::None))
+            .collect()
+    }
+
+    #[test]
+    fn a_label_renames_the_device_but_not_its_vendor() {
+        let [labeled, randomized, unlabeled] = scan_with_labels("record_labels", &["00:1B:63:84:45:E6", "02:00:00:00:00:01", "00:1B:63:84:45:E7"])
+            .try_into()
+            .unwrap();
+
+        assert_eq!((labeled.manufacturer.as_deref(), labeled.label.as_deref()), (Some("Front desk"), Some("Front desk")));
+        assert!(labeled.manufacturer_resolved);
+        assert_eq!(labeled.vendor.as_deref(), Some("Apple"));
+
+        assert_eq!(randomized.label.as_deref(), Some("Test phone"));
+        assert!(!randomized.manufacturer_resolved);
+        assert_eq!(randomized.vendor, None);
+
+        assert_eq!((unlabeled.manufacturer.as_deref(), unlabeled.label), (Some("Apple, Inc."), None));
+    }
+
+    #[test]
+    fn manufacturer_counts_skip_labels_and_randomized_macs() {
+        let macs = ["00:1B:63:84:45:E6", "00:1B:63:84:45:E7", "02:00:00:00:00:01", "02:00:00:00:00:02", "00:00:01:00:00:01"];
+        let mut wifi_data = scan_with_labels("summary_labels", &macs);
+        // A record read back from an older document can carry a vendor on a randomized MAC
+        wifi_data[3].vendor = Some("Apple".to_string());
+
+        let summary = Summary::new(&wifi_data, Utc::now(), Utc::now());
+
+        assert_eq!(summary.manufacturer_counts, BTreeMap::from([("Apple".to_string(), 2)]));
+        assert_eq!(summary.randomized_macs, 2);
+    }
+
+    #[test]
+    fn scheduled_records_enrich_like_instant_ones() {
+        let csv = "Registry,Assignment,Organization Name,Organization Address\n\
+                   MA-L,001B63,\"Apple, Inc.\",1 Infinite Loop Cupertino CA US 95014\n";
+        let oui = OuiDatabase::from_reader(csv.as_bytes()).unwrap();
+        let config: Config = serde_json::from_str(r#"{ "timestamp_format": "absolute" }"#).unwrap();
+        let first = tokio_wifiscanner::Wifi {
+            mac: "00:1b:63:84:45:e6".to_string(),
+            ssid: "Office".to_string(),
+            channel: "36".to_string(),
+            signal_level: "-60".to_string(),
+            security: "WPA2(PSK/AES/AES)".to_string(),
+        };
+        let latest = tokio_wifiscanner::Wifi { signal_level: "-50".to_string(), ..first.clone() };
+        let now = Sighting::now();
+        let mut track = DeviceTrack::new(first, now, 1);
+        track.observe(latest.clone(), now, 2, 15);
+        let tracks = HashMap::from([(normalize_mac(&latest.mac), track)]);
+
+        let scheduled = generate_results(&tracks, 2, &oui, &config).remove(0);
+
+        let instant = WifiData::from_scan(&latest, &oui, config.sanitize);
+        let stamp = now.timestamp.to_rfc3339_opts(SecondsFormat::Secs, true);
+        assert_eq!(scheduled.wifi_durations, format!("{}/{}", stamp, stamp));
+        assert_eq!((scheduled.signal_min, scheduled.signal_max), (Some(-60), Some(-50)));
+        assert_eq!((scheduled.sighting_count, scheduled.stability), (Some(2), Some(1.0)));
+        let scheduled_only_cleared = WifiData {
+            signal_min: None,
+            signal_max: None,
+            signal_avg: None,
+            wifi_durations: String::new(),
+            first_seen: None,
+            last_seen: None,
+            total_dwell_secs: None,
+            sighting_count: None,
+            stability: None,
+            ..scheduled
+        };
+        assert_eq!(scheduled_only_cleared, instant);
+    }
+
+    #[test]
+    fn kml_places_located_networks_only() {
+        let located = WifiData {
+            ssid: "Joe's <Cafe>".to_string(),
+            mac: "00:1B:63:84:45:E6".to_string(),
+            channel: 6,
+            location: Some(Location { latitude: 51.5, longitude: -0.12, altitude: None, fix_time: None }),
+            ..WifiData::default()
+        };
+        let unlocated = WifiData { ssid: "Nowhere".to_string(), ..WifiData::default() };
+        let mut output = Vec::new();
+        write_kml(&[located, unlocated], &mut output).unwrap();
+        let kml = String::from_utf8(output).unwrap();
+        assert_eq!(kml.matches("<Placemark>").count(), 1);
+        assert!(kml.contains("<name>Joe&apos;s &lt;Cafe&gt;</name>"));
+        assert!(kml.contains("<coordinates>-0.12,51.5</coordinates>"));
+        assert!(!kml.contains("Nowhere"));
+    }
+
+    #[test]
+    fn vendor_variants_share_one_name() {
+        for raw in ["Apple, Inc.", "Apple Inc", "APPLE INC", "  Apple   Inc. "] {
+            assert_eq!(normalize_vendor(raw), "Apple");
+        }
+        assert_eq!(normalize_vendor("Samsung Electronics Co.,Ltd"), "Samsung");
+        assert_eq!(normalize_vendor("Raspberry Pi Trading Ltd"), "Raspberry Pi Trading");
+        assert_eq!(normalize_vendor("Espressif Inc."), "Espressif");
+        assert_eq!(normalize_vendor("Inc"), "Inc");
+    }
+
+    #[test]
+    fn channel_to_band_boundaries() {
+        assert_eq!(channel_to_band(1), "2.4GHz");
+        assert_eq!(channel_to_band(14), "2.4GHz");
+        assert_eq!(channel_to_band(15), "Unknown");
+        assert_eq!(channel_to_band(36), "5GHz");
+        assert_eq!(channel_to_band(165), "5GHz");
+        assert_eq!(channel_to_band(181), "6GHz");
+        assert_eq!(channel_to_band(0), "Unknown");
+        // The 6 GHz numbers between the 2.4 and 5 GHz ranges, and past the end of 5 GHz
+        assert_eq!(channel_to_band(17), "6GHz");
+        assert_eq!(channel_to_band(29), "6GHz");
+        assert_eq!(channel_to_band(18), "Unknown");
+        assert_eq!(channel_to_band(31), "Unknown");
+        assert_eq!(channel_to_band(32), "5GHz");
+        assert_eq!(channel_to_band(177), "5GHz");
+        assert_eq!(channel_to_band(179), "Unknown");
+        assert_eq!(channel_to_band(233), "6GHz");
+        assert_eq!(channel_to_band(237), "Unknown");
+    }
+
+    #[test]
+    fn congestion_keeps_6ghz_channels_apart_from_5ghz_ones() {
+        let on = |channel: i32, frequency_mhz: u32| WifiData {
+            channel,
+            channels: vec![channel],
+            frequency_mhz: Some(frequency_mhz),
+            ..WifiData::default()
+        };
+        let congestion = ChannelCongestion::new(&[on(149, 5745), on(149, 6695), on(37, 6135), on(6, 2437)]);
+        assert_eq!(congestion.channels["5GHz"][&149].aps, 1);
+        assert_eq!(congestion.channels["6GHz"][&149].aps, 1);
+        assert_eq!(congestion.channels["6GHz"][&37].aps, 1);
+        assert!(!congestion.channels["5GHz"].contains_key(&37));
+        assert_eq!(congestion.recommended_channel, 1);
+    }
+
+    #[test]
+    fn locally_administered_bit_marks_randomized_macs() {
+        assert!(is_locally_administered("DA:A1:19:00:00:01"));
+        assert!(is_locally_administered("02:00:00:00:00:01"));
+        assert!(!is_locally_administered("00:1B:63:84:45:E6"));
+        assert!(!is_locally_administered(""));
+    }
+
+    #[test]
+    fn empty_ssid_is_hidden() {
+        assert_eq!(ssid_for_output("", SanitizeMode::default()), (HIDDEN_SSID.to_string(), true));
+        assert_eq!(ssid_for_output(" \u{0}", SanitizeMode::default()), (HIDDEN_SSID.to_string(), true));
+        assert_eq!(ssid_for_output("Lobby", SanitizeMode::default()), ("Lobby".to_string(), false));
+    }
+
+    #[test]
+    fn channel_and_frequency_fill_each_other_in() {
+        assert_eq!(channel_and_frequency("6"), (6, Some(2437)));
+        assert_eq!(channel_and_frequency("36"), (36, Some(5180)));
+        assert_eq!(channel_and_frequency("5180"), (36, Some(5180)));
+        assert_eq!(channel_and_frequency("6135"), (37, Some(6135)));
+        assert_eq!(channel_and_frequency(""), (0, None));
+    }
+
+    #[test]
+    fn security_parse_separates_owe_from_open() {
+        assert_eq!(Security::parse(""), Security::Open);
+        assert_eq!(Security::parse("RSN(OWE)"), Security::Owe);
+        assert_eq!(Security::parse("OWE"), Security::Owe);
+        assert_eq!(Security::parse("RSN(PSK SAE)"), Security::Wpa2Wpa3Mixed);
+        assert_eq!(Security::parse("RSN(SAE)"), Security::Wpa3);
+    }
+
+    #[test]
+    fn missing_config_is_written_with_the_defaults() {
+        let path = std::env::temp_dir().join(format!("wifi_module_default_config_{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let config = read_config(path.to_str().unwrap(), None).unwrap();
+        assert!(config.instant_scan);
+        assert_eq!(config.scan_duration, default_scan_duration());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), DEFAULT_CONFIG);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn toml_config_matches_the_json_one() {
+        let path = std::env::temp_dir().join(format!("wifi_module_default_config_{}.toml", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        read_config(path.to_str().unwrap(), None).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), DEFAULT_CONFIG_TOML);
+        std::fs::write(&path, "[profiles.office]\nscan_duration = 120\nssid_filter = [\"Office\"]\n").unwrap();
+        let config = read_config(path.to_str().unwrap(), Some("office")).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(config.scan_duration, 120);
+        assert_eq!(config.ssid_filter, Some(vec!["Office".to_string()]));
+
+        // The two defaults carry the same settings once the JSON comment keys are dropped
+        let mut json: serde_json::Value = serde_json::from_str(DEFAULT_CONFIG).unwrap();
+        json.as_object_mut().unwrap().retain(|key, _| !key.starts_with("_comment"));
+        let toml: serde_json::Value = toml::from_str(DEFAULT_CONFIG_TOML).unwrap();
+        assert_eq!(toml, json);
+    }
+
+    #[test]
+    fn schedule_accepts_six_and_five_field_cron() {
+        let schedule = |expression: &str| {
+            let config: Config = serde_json::from_value(serde_json::json!({ "schedule": expression })).unwrap();
+            config.cron_schedule()
+        };
+        let hourly = schedule("0 * * * *").unwrap().unwrap();
+        let next = hourly.upcoming(Utc).next().unwrap();
+        assert_eq!(next.format("%M:%S").to_string(), "00:00");
+        assert!(schedule("30 */5 * * * *").unwrap().is_some());
+        assert!(matches!(schedule("every hour"), Err(ScanError::ConfigInvalid(_))));
+        let unset: Config = serde_json::from_str("{}").unwrap();
+        assert!(unset.cron_schedule().unwrap().is_none());
+    }
+
+    #[test]
+    fn env_overrides_replace_file_values() {
+        let mut value = serde_json::json!({ "instant_scan": true, "scan_duration": 30, "output": "file.json" });
+        let vars = [
+            ("WIFI_INSTANT_SCAN", "false"),
+            ("WIFI_START_AFTER", "10"),
+            ("WIFI_OUTPUT", "env.json"),
+            ("WIFI_SSID_FILTER", r#"["Office"]"#),
+            ("HOME", "/root"),
+        ];
+        apply_env_overrides(&mut value, vars.iter().map(|(name, raw)| (name.to_string(), raw.to_string()))).unwrap();
+        let config: Config = serde_json::from_value(value).unwrap();
+        assert!(!config.instant_scan);
+        assert_eq!(config.start_after_duration, 10);
+        assert_eq!(config.scan_duration, 30);
+        assert_eq!(config.output.as_deref(), Some("env.json"));
+        assert_eq!(config.ssid_filter, Some(vec!["Office".to_string()]));
+    }
+
+    #[test]
+    fn numeric_env_values_stay_strings_for_string_fields() {
+        let mut value = serde_json::json!({});
+        let vars = [("WIFI_HASH_MAC", "true"), ("WIFI_MAC_SALT", "20240101"), ("WIFI_STOP_ON_SSID", "1234")];
+        apply_env_overrides(&mut value, vars.iter().map(|(name, raw)| (name.to_string(), raw.to_string()))).unwrap();
+        let config: Config = serde_json::from_value(value).unwrap();
+        assert!(config.hash_mac);
+        assert_eq!(config.mac_salt.as_deref(), Some("20240101"));
+        assert_eq!(config.stop_on_ssid.as_deref(), Some("1234"));
+
+        let mut value = serde_json::json!({});
+        let bad = [("WIFI_SCAN_DURATION", "soon".to_string())];
+        let error = apply_env_overrides(&mut value, bad.into_iter().map(|(name, raw)| (name.to_string(), raw))).unwrap_err();
+        assert!(matches!(error, ScanError::ConfigInvalid(message) if message.starts_with("WIFI_SCAN_DURATION")));
+    }
+
+    #[test]
+    fn normalize_mac_accepts_common_separators() {
+        for raw in ["a4:5e:60:c2:1f:0b", "A4-5E-60-C2-1F-0B", "a45e.60c2.1f0b", " a4:5E:60:c2:1f:0B "] {
+            assert_eq!(normalize_mac(raw), "A4:5E:60:C2:1F:0B");
+        }
+        assert_eq!(normalize_mac("not-a-mac"), "NOT-A-MAC");
+    }
+
+    #[test]
+    fn sanitize_strips_control_characters() {
+        assert_eq!(SanitizeMode::ControlCharsOnly.apply("Lobby\u{0}\n\tWiFi"), "LobbyWiFi");
+        assert_eq!(SanitizeMode::None.apply("Lobby\n"), "Lobby\n");
+        assert_eq!(SanitizeMode::Aggressive.apply("Joe's Café'; DROP--"), "Joes Café DROP--");
+    }
+
+    #[test]
+    fn signal_bucketing_rounds_or_labels_readings() {
+        let parse = |value: &str| serde_json::from_value::<SignalBucketing>(serde_json::json!(value));
+        assert_eq!(parse("bins:5").unwrap(), SignalBucketing::Bins(5));
+        assert!(parse("bins:0").is_err() && parse("loud").is_err());
+
+        let reading = WifiData {
+            signal_dbm: Some(-63),
+            signal_min: Some(-71),
+            signal_max: Some(-60),
+            signal_avg: Some(-64.5),
+            ..WifiData::default()
+        };
+        let mut binned = reading.clone();
+        SignalBucketing::Bins(5).apply(&mut binned);
+        assert_eq!((binned.signal_dbm, binned.signal_min, binned.signal_max), (Some(-65), Some(-75), Some(-60)));
+        assert_eq!(binned.signal_avg, Some(-65.0));
+
+        let mut coarse = reading.clone();
+        SignalBucketing::Coarse.apply(&mut coarse);
+        assert_eq!(coarse.signal_bucket.as_deref(), Some("medium"));
+        assert_eq!((coarse.signal_dbm, coarse.signal_min, coarse.signal_max, coarse.signal_avg), (None, None, None, None));
+
+        let mut exact = reading.clone();
+        SignalBucketing::Exact.apply(&mut exact);
+        assert_eq!(exact, reading);
+    }
+}
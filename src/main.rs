@@ -1,219 +1,854 @@
-use serde::{Deserialize, Serialize};
-use serde_json::{json, Value};
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::{Read, Write};
-use std::time::Instant;
-use tokio_wifiscanner;
-use csv;
-
-#[derive(Serialize)]
-struct WifiData {
-    ssid: String,
-    mac: String,
-    manufacturer: Option<String>,
-    network_security: String,
-    channel: i32,
-    wifi_durations: String,  
-}
-
-#[derive(Deserialize)]
-struct Config {
-    instant_scan: bool,
-    start_after_duration: Option<u64>,
-    scan_duration: Option<u64>,
+#[cfg(feature = "gpsd")]
+use wifi_module::gpsd::current_fix;
+#[cfg(feature = "metrics")]
+use wifi_module::metrics::Metrics;
+#[cfg(feature = "mqtt")]
+use wifi_module::mqtt::MqttPublisher;
+#[cfg(feature = "update-oui")]
+use wifi_module::oui_update::{is_stale, update_oui};
+#[cfg(feature = "webhook")]
+use wifi_module::webhook::WebhookClient;
+use chrono::{SecondsFormat, Utc};
+use std::collections::HashSet;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::process::ExitCode;
+use tracing::{error, info, warn};
+use tracing_subscriber::EnvFilter;
+use wifi_module::interface::{ensure_exists, list_interfaces};
+use wifi_module::{
+    compressed_name, create_output, read_config_with, read_results, results_document, results_schema, to_json, sqlite_path, scan_once, scan_scheduled_watch, ctrl_c_pressed, write_csv_to_file, write_json_to_file, write_kml_to_file, write_ndjson,
+    write_ndjson_to_file, write_wigle_to_file,
+    Config, Departure, OutputFormat, PresenceTracker, GZIP_SUFFIX, STDOUT_TARGET, ScanDelta, OuiDatabase, ScanError, Scanner, Summary, SystemScanner, TimedScanner, WifiData,
+};
+
+// Shown after the option list of --help
+const HELP_NOTES: &str = "Config precedence, highest first: command line flags, WIFI_<FIELD> environment variables
+(e.g. WIFI_SCAN_DURATION=120), the --config file, then the built-in defaults. A missing config file is
+created with the defaults on first run; one ending in .toml is read as TOML, anything else as JSON.
+
+Output formats (output_format in the config): json (default), csv, both (json plus a .csv beside it),
+ndjson, wigle (WiGLE CSV) and kml. JSON documents number the records (\"1\": {...}) unless output_shape
+is \"array\". An --output ending in .gz is gzip-compressed, - writes to stdout, and sqlite://path.db
+records into a database with the sqlite feature. Progress is logged to stderr, so stdout only carries results.
+
+With a cron `schedule` in the config, scan stays running and repeats at each scheduled time. Scheduled
+runs and monitor snapshots each get a timestamped file in output_dir (--output-dir), unless output is - or sqlite://.
+With report_departures set, monitor writes a {\"event\": \"departed\", ...} line when a device leaves: into the
+NDJSON stream (file or -) when there is one, where only these lines carry an \"event\" field, otherwise to stdout.
+
+Exit status: 0 success, 2 bad arguments, 3 config error, 4 OUI download error, 5 scan error,
+6 output error, 7 the scan found no networks, 8 the stop_on_mac/stop_on_ssid target never showed up.";
+
+// Running without a subcommand is the same as `scan`
+#[derive(clap::Parser)]
+#[command(name = "wifi_module", version, about = "Scan nearby WiFi networks and enrich them with vendor, band and presence data")]
+#[command(after_long_help = HELP_NOTES)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    scan: ScanArgs,
+    #[arg(long, global = true, value_name = "PATH", default_value = "config.json", help = "Config file to read, JSON or .toml")]
+    config: String,
+    #[arg(long, global = true, value_name = "NAME", help = "Named profile to use when the config file defines several")]
+    profile: Option<String>,
+    #[arg(long, global = true, value_name = "PATH", help = "OUI registry CSV [default: src/database/oui.csv, or the built-in copy]")]
+    oui: Option<String>,
+    #[arg(long, global = true, help = "Log debug output; RUST_LOG takes precedence when set")]
+    verbose: bool,
+    #[arg(long, global = true, conflicts_with = "verbose", help = "Suppress progress messages, leaving only errors")]
+    quiet: bool,
+    // Spellings from before the subcommands, kept so existing scripts still work
+    #[arg(long, hide = true)]
+    once: bool,
+    #[arg(long, hide = true, conflicts_with = "once")]
+    monitor: bool,
+    #[arg(long, hide = true)]
+    list_interfaces: bool,
+    #[arg(long, hide = true)]
+    print_schema: bool,
 }
 
-#[tokio::main]
-async fn main() {
-    match run_wifi_script().await {
-        Ok(result) => {
-            if result {
-                println!("WiFi data script executed successfully.");
-            } else {
-                println!("No data was processed.");
+#[derive(clap::Subcommand)]
+enum Command {
+    #[command(about = "Run the instant or scheduled scan from the config once, or on its `schedule` (the default)")]
+    Scan(ScanArgs),
+    #[command(about = "Scan every monitor_interval_secs until Ctrl-C, one wifi_monitor_<timestamp> snapshot per cycle")]
+    Monitor(TargetArgs),
+    #[command(about = "Download the current IEEE registry to --oui (default src/database/oui.csv)")]
+    UpdateOui {
+        #[arg(long, value_name = "DAYS", help = "Only download when the existing file is older than this many days")]
+        if_older_than: Option<u64>,
+    },
+    #[command(about = "Print what was added, removed and changed between two JSON results files")]
+    Compare {
+        #[arg(help = "Earlier results")]
+        previous: String,
+        #[arg(help = "Later results")]
+        current: String,
+    },
+    #[command(about = "List the wireless interfaces (Linux only)")]
+    ListInterfaces,
+    #[command(about = "Print the JSON Schema of the results document")]
+    PrintSchema,
+}
+
+// Where and how a scan or monitor run reads and writes
+#[derive(clap::Args, Default, PartialEq)]
+struct TargetArgs {
+    #[arg(long, help = "Wireless interface to scan (Linux only), overriding the config's `interface`")]
+    interface: Option<String>,
+    #[arg(long, value_name = "N", help = "Back-to-back scans per instant or monitor cycle, keeping each network's strongest signal")]
+    scan_count: Option<u32>,
+    #[arg(long, value_name = "PATH", help = "File to write, - for stdout, or sqlite://path.db")]
+    output: Option<String>,
+    #[arg(long, value_name = "DIR", help = "Directory for timestamped result files, overriding the config's `output_dir`")]
+    output_dir: Option<String>,
+    #[arg(long, help = "Add to an existing ndjson, csv or wigle output file instead of replacing it")]
+    append: bool,
+}
+
+#[derive(clap::Args, Default, PartialEq)]
+struct ScanArgs {
+    #[command(flatten)]
+    target: TargetArgs,
+    #[arg(long, help = "Print the results without writing any output file")]
+    dry_run: bool,
+    #[arg(long, help = "Print only the number of distinct BSSIDs found, writing no file and logging only errors")]
+    count: bool,
+    #[arg(long, requires = "count", help = "Leave locally administered (randomized) MACs out of the --count")]
+    skip_randomized: bool,
+    #[arg(long, value_name = "PATH", help = "Earlier JSON results to diff against, adding a \"delta\" section")]
+    compare: Option<String>,
+}
+
+// Where the OUI database lives in the source tree, read when --oui isn't given
+const DEFAULT_OUI_PATH: &str = "src/database/oui.csv";
+
+// How long to wait for queued messages to go out before exiting
+#[cfg(feature = "mqtt")]
+const PUBLISH_FLUSH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+#[derive(PartialEq)]
+enum Mode {
+    Once,
+    Monitor,
+    ListInterfaces,
+    PrintSchema,
+    UpdateOui,
+    Compare,
+}
+
+// The parsed command line, flattened for the run functions
+struct Args {
+    mode: Mode,
+    config: String,
+    profile: Option<String>,
+    interface: Option<String>,
+    scan_count: Option<u32>,
+    oui: Option<String>,
+    output: Option<String>,
+    output_dir: Option<String>,
+    append: bool,
+    dry_run: bool,
+    count: bool,
+    skip_randomized: bool,
+    compare: Option<String>,
+    #[cfg_attr(not(feature = "update-oui"), allow(dead_code))]
+    if_older_than: Option<u64>,
+    // Later results for the compare subcommand, `compare` holding the earlier ones
+    compare_with: Option<String>,
+    verbose: bool,
+    quiet: bool,
+}
+
+impl Cli {
+    // clap's args_conflicts_with_subcommands would also reject the global options before a subcommand, so
+    // the scan options and legacy mode flags given without one are checked here instead
+    fn try_parse() -> Result<Cli, clap::Error> {
+        let cli = <Cli as clap::Parser>::try_parse()?;
+        let top_level = cli.scan != ScanArgs::default() || cli.once || cli.monitor || cli.list_interfaces || cli.print_schema;
+        if cli.command.is_some() && top_level {
+            let message = "scan options go after the subcommand, e.g. `wifi_module scan --output results.json`";
+            return Err(<Cli as clap::CommandFactory>::command().error(clap::error::ErrorKind::ArgumentConflict, message));
+        }
+        Ok(cli)
+    }
+}
+
+impl From<Cli> for Args {
+    fn from(cli: Cli) -> Self {
+        let legacy_mode = match (cli.monitor, cli.list_interfaces, cli.print_schema) {
+            (_, true, _) => Mode::ListInterfaces,
+            (_, _, true) => Mode::PrintSchema,
+            (true, _, _) => Mode::Monitor,
+            _ => Mode::Once,
+        };
+        let (mode, scan, if_older_than, compare_with) = match cli.command {
+            None => (legacy_mode, cli.scan, None, None),
+            Some(Command::Scan(scan)) => (Mode::Once, scan, None, None),
+            Some(Command::Monitor(target)) => (Mode::Monitor, ScanArgs { target, ..ScanArgs::default() }, None, None),
+            Some(Command::UpdateOui { if_older_than }) => (Mode::UpdateOui, ScanArgs::default(), if_older_than, None),
+            Some(Command::Compare { previous, current }) => {
+                (Mode::Compare, ScanArgs { compare: Some(previous), ..ScanArgs::default() }, None, Some(current))
             }
+            Some(Command::ListInterfaces) => (Mode::ListInterfaces, ScanArgs::default(), None, None),
+            Some(Command::PrintSchema) => (Mode::PrintSchema, ScanArgs::default(), None, None),
+        };
+        Args {
+            mode,
+            config: cli.config,
+            profile: cli.profile,
+            interface: scan.target.interface,
+            scan_count: scan.target.scan_count,
+            oui: cli.oui,
+            output: scan.target.output,
+            output_dir: scan.target.output_dir,
+            append: scan.target.append,
+            dry_run: scan.dry_run,
+            count: scan.count,
+            skip_randomized: scan.skip_randomized,
+            compare: scan.compare,
+            if_older_than,
+            compare_with,
+            verbose: cli.verbose,
+            quiet: cli.quiet,
         }
-        Err(e) => println!("Error occurred: {}", e),
     }
 }
 
-pub async fn run_wifi_script() -> Result<bool, Box<dyn std::error::Error>> {
-    let mut file = File::open("config.json")?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
-    let config: Config = serde_json::from_str(&contents)?;
+// Exit statuses not tied to a ScanError, which maps its own through `exit_code`
+const EXIT_USAGE: u8 = 2;
+const EXIT_NO_DATA: u8 = 7;
+const EXIT_TARGET_MISSED: u8 = 8;
 
-    if config.instant_scan {
-        println!("\nScan was set to be instant, starting scan...");
-        let networks = scan().await?;
-        let oui_data = read_oui_csv("src/database/oui.csv")?;
-        let wifi_data = convert_to_wifi_data(&networks, &oui_data);
+// How a run that didn't fail ended
+enum Outcome {
+    // A utility command such as list-interfaces finished
+    Done,
+    Saved,
+    NoData,
+    // A scheduled scan with `stop_on_mac`/`stop_on_ssid` saw, or never saw, its target
+    TargetSpotted,
+    TargetMissed,
+}
+
+impl Outcome {
+    fn of(wifi_data: &[WifiData]) -> Outcome {
+        if wifi_data.is_empty() { Outcome::NoData } else { Outcome::Saved }
+    }
+}
 
-        let mut formatted_wifi_data = serde_json::Map::new();
-        for (i, data) in wifi_data.iter().enumerate() {
-            let key = (i + 1).to_string();
-            formatted_wifi_data.insert(key, serde_json::to_value(data)?);
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Args = match Cli::try_parse() {
+        Ok(cli) => cli.into(),
+        // --help and --version land here too, printed to stdout with a success status
+        Err(e) => {
+            let _ = e.print();
+            return if e.use_stderr() { ExitCode::from(EXIT_USAGE) } else { ExitCode::SUCCESS };
+        }
+    };
+    init_logging(&args);
+    let outcome = match args.mode {
+        Mode::UpdateOui => run_update_oui(&args).await.map(|()| Outcome::Done),
+        Mode::ListInterfaces => list_interfaces().await.map(|interfaces| {
+            interfaces.iter().for_each(|interface| println!("{}", interface));
+            Outcome::Done
+        }),
+        Mode::PrintSchema => to_json(&results_schema(), true).map(|schema| {
+            println!("{}", schema);
+            Outcome::Done
+        }),
+        Mode::Compare => compare_files(&args).map(|()| Outcome::Done),
+        Mode::Once | Mode::Monitor => run_wifi_script(&args).await,
+    };
+    match outcome {
+        Ok(Outcome::Done) => ExitCode::SUCCESS,
+        Ok(Outcome::Saved) => {
+            info!("WiFi data script executed successfully.");
+            ExitCode::SUCCESS
+        }
+        Ok(Outcome::TargetSpotted) => {
+            info!("The watched device showed up, results were saved early.");
+            ExitCode::SUCCESS
         }
+        Ok(Outcome::TargetMissed) => {
+            warn!("The watched device never showed up.");
+            ExitCode::from(EXIT_TARGET_MISSED)
+        }
+        Ok(Outcome::NoData) => {
+            warn!("No data was processed.");
+            ExitCode::from(EXIT_NO_DATA)
+        }
+        Err(e) => {
+            error!("Error occurred: {}", e);
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
 
-        let json_data = serde_json::to_string_pretty(&formatted_wifi_data)?;
-        println!("{}", json_data);
-        write_json_to_file(&json_data, "wifi_instantdata.json")?;
-        Ok(!json_data.is_empty())
-    } else {
-        println!("\nScan was set to be delayed");
-        let oui_data = read_oui_csv("src/database/oui.csv")?;
-        let start_after_duration = config.start_after_duration.unwrap_or(0);
-        let scan_duration = config.scan_duration.unwrap_or(60);
-        for i in (1..=start_after_duration).rev() {
-            println!("Scan starts in {} seconds", i);
-            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-        }
-
-        println!("Scan started, it will last for {} seconds...", scan_duration);
-        let scan_start_time = Instant::now();
-        let mut device_intervals: HashMap<String, Vec<(Instant, Instant)>> = HashMap::new();
-        let mut last_seen: HashMap<String, Instant> = HashMap::new();
-
-        let mut networks = Vec::new();
-        while Instant::now().duration_since(scan_start_time) < tokio::time::Duration::from_secs(scan_duration) {
-            networks = scan().await?;
-
-            for network in networks.iter() {
-                let now = Instant::now();
-                let device_id = &network.mac;
-                let device_last_seen = last_seen.entry(device_id.clone()).or_insert(now);
-
-                if now.duration_since(*device_last_seen).as_secs() > 5 {
-                    if let Some(intervals) = device_intervals.get_mut(device_id) {
-                        intervals.push((*device_last_seen, now));
-                    } else {
-                        device_intervals.insert(device_id.clone(), vec![(*device_last_seen, now)]);
-                    }
-                }
-                *device_last_seen = now;
+// Human-facing messages all go to stderr so redirecting stdout captures clean JSON
+fn init_logging(args: &Args) {
+    // --count keeps stderr as quiet as stdout unless --verbose asks otherwise
+    let quiet = args.quiet || (args.count && !args.verbose);
+    let default_level = match (quiet, args.verbose) {
+        (true, _) => "error",
+        (false, true) => "debug",
+        (false, false) => "info",
+    };
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_level));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal())
+        .init();
+}
+
+// Live integrations fed after every scan or monitor cycle, on top of the file output
+struct Publishers {
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
+    #[cfg(feature = "mqtt")]
+    mqtt: Option<MqttPublisher>,
+    #[cfg(feature = "webhook")]
+    webhook: Option<WebhookClient>,
+}
+
+impl Publishers {
+    fn connect(config: &Config) -> Publishers {
+        #[cfg(not(feature = "mqtt"))]
+        if config.mqtt.is_some() {
+            warn!("An mqtt block is configured but this build lacks the mqtt feature, skipping it");
+        }
+        #[cfg(not(feature = "webhook"))]
+        if config.webhook_url.is_some() {
+            warn!("A webhook_url is configured but this build lacks the webhook feature, skipping it");
+        }
+        #[cfg(not(feature = "metrics"))]
+        if config.metrics_port.is_some() {
+            warn!("A metrics_port is configured but this build lacks the metrics feature, skipping it");
+        }
+        Publishers {
+            #[cfg(feature = "metrics")]
+            metrics: config.metrics_port.map(|port| {
+                let metrics = Metrics::default();
+                metrics.serve(port);
+                metrics
+            }),
+            #[cfg(feature = "mqtt")]
+            mqtt: config.mqtt.as_ref().map(MqttPublisher::connect),
+            #[cfg(feature = "webhook")]
+            webhook: config.webhook_url.as_deref().and_then(|url| {
+                let timeout = std::time::Duration::from_secs(config.webhook_timeout_secs);
+                WebhookClient::new(url, timeout, config.webhook_retries)
+                    .map_err(|e| warn!("{}, webhook disabled", e))
+                    .ok()
+            }),
+        }
+    }
+
+    // Failures are reported but never stop the scan
+    #[cfg_attr(not(feature = "webhook"), allow(unused_variables))]
+    async fn publish(&self, mode: &str, wifi_data: &[WifiData]) {
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.record_scan(wifi_data);
+        }
+        #[cfg(feature = "mqtt")]
+        if let Some(mqtt) = &self.mqtt {
+            if let Err(e) = mqtt.publish(wifi_data).await {
+                warn!("{}", e);
+            }
+        }
+        #[cfg(feature = "webhook")]
+        if let Some(webhook) = &self.webhook {
+            if let Err(e) = webhook.post(mode, wifi_data).await {
+                warn!("{}", e);
+            }
+        }
+    }
+
+    // Departures go out as JSON lines as well as to the MQTT and webhook publishers. An NDJSON monitor
+    // interleaves them with its records, which they're told apart from by their "event" field; otherwise
+    // they're the only thing on stdout.
+    async fn publish_departures(&self, departures: &[Departure], stream: Option<&mut (dyn Write + 'static)>) -> Result<(), ScanError> {
+        let mut stdout = std::io::stdout();
+        let writer = stream.unwrap_or(&mut stdout);
+        for departure in departures {
+            info!("{} left after {}s", departure.mac, departure.dwell_secs);
+            writeln!(writer, "{}", serde_json::to_string(departure).map_err(ScanError::Serialize)?).map_err(ScanError::Output)?;
+        }
+        writer.flush().map_err(ScanError::Output)?;
+        #[cfg(feature = "mqtt")]
+        if let Some(mqtt) = &self.mqtt {
+            if let Err(e) = mqtt.publish_departures(departures).await {
+                warn!("{}", e);
             }
-            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-        }
-
-    // Generate ID-ed results based on device_intervals
-    let mut formatted_wifi_data = serde_json::Map::new();
-    let mut id = 1;
-    for (mac, intervals) in &device_intervals {
-        let durations = intervals.iter()
-            .map(|(start, end)| format!("{}-{}", start.elapsed().as_secs(), end.elapsed().as_secs()))
-            .collect::<Vec<String>>().join(",");
-        let network = networks.iter().find(|n| n.mac == *mac).unwrap(); // Safe unwrap because mac comes from scanned networks
-        let manufacturer = get_manufacturer(&network.mac, &oui_data).unwrap_or_else(|| "Unknown".to_string());
-        
-        let wifi_data_item = json!({
-            "ssid": sanitize_string(&network.ssid),
-            "mac": network.mac,
-            "manufacturer": manufacturer,
-            "network_security": if network.security.is_empty() { "Open" } else { "Secured" },
-            "channel": network.channel,
-            "wifi_durations": durations
-        });
-
-        formatted_wifi_data.insert(id.to_string(), wifi_data_item);
-        id += 1;
-    }
-
-    let json_data = serde_json::to_string_pretty(&formatted_wifi_data)?;
-    println!("{}", json_data);
-    write_json_to_file(&json_data, "wifi_scheduleddata.json")?;
-
-    Ok(true)
-    }
-}
-
-fn convert_to_wifi_data(networks: &[tokio_wifiscanner::Wifi], oui_data: &HashMap<String, String>) -> Vec<serde_json::Value> {
-    networks.iter().map(|network| {
-        let raw_manufacturer = get_manufacturer(&network.mac, oui_data).unwrap_or_else(|| "Unknown".to_string());
-        let manufacturer = sanitize_string(&raw_manufacturer);
-        let network_security = if network.security.is_empty() { "Open" } else { "Secured" };
-        let ssid_sanitized = sanitize_string(&network.ssid);
-        let wifi_data_item = json!({
-            "ssid": ssid_sanitized,
-            "mac": network.mac,
-            "manufacturer": manufacturer,
-            "network_security": network_security,
-            "channel": network.channel,
-            "wifi_durations": "" 
-        });
-        wifi_data_item
-    }).collect()
-}
-
-fn sanitize_string(input: &str) -> String {
-    input.replace("'", " ").replace("`", " ").replace("\"", " ")
-}
-
-fn get_manufacturer(mac: &str, oui_data: &HashMap<String, String>) -> Option<String> {
-    let mac_prefix = mac.split(':').take(3).collect::<String>().to_uppercase();
-    oui_data.get(&mac_prefix).cloned().or(Some("Unknown".to_string()))
-}
-
-fn write_json_to_file(json_data: &str, filename: &str) -> Result<(), std::io::Error> {
-    let mut file = File::create(filename)?;
-    file.write_all(json_data.as_bytes())?;
+        }
+        #[cfg(feature = "webhook")]
+        if let Some(webhook) = &self.webhook {
+            if let Err(e) = webhook.post_departures(departures).await {
+                warn!("{}", e);
+            }
+        }
+        Ok(())
+    }
+
+    async fn close(self) {
+        #[cfg(feature = "mqtt")]
+        if let Some(mqtt) = self.mqtt {
+            mqtt.close(PUBLISH_FLUSH_TIMEOUT).await;
+        }
+    }
+}
+
+// An explicit --oui always wins; otherwise use the embedded registry when it was compiled in.
+// The config's custom_labels are layered on top.
+// Vendor names are only enrichment, so a registry or labels file that can't be loaded is logged and the scan
+// goes on: with the built-in registry when there is one, otherwise with every manufacturer as Unknown
+fn load_oui(args: &Args, config: &Config) -> OuiDatabase {
+    let loaded = match &args.oui {
+        #[cfg(feature = "embedded-oui")]
+        Some(path) => OuiDatabase::load_from_csv(path).or_else(|e| {
+            warn!("{}, using the built-in registry instead", e);
+            OuiDatabase::load_embedded()
+        }),
+        #[cfg(not(feature = "embedded-oui"))]
+        Some(path) => OuiDatabase::load_from_csv(path),
+        #[cfg(feature = "embedded-oui")]
+        None => OuiDatabase::load_embedded(),
+        #[cfg(not(feature = "embedded-oui"))]
+        None => OuiDatabase::load_from_csv(DEFAULT_OUI_PATH),
+    };
+    let oui_data = loaded.unwrap_or_else(|e| {
+        warn!("{}, manufacturers will be reported as Unknown", e);
+        OuiDatabase::default()
+    });
+    match &config.custom_labels {
+        Some(path) => oui_data.clone().with_custom_labels(path).unwrap_or_else(|e| {
+            warn!("{}, continuing without them", e);
+            oui_data
+        }),
+        None => oui_data,
+    }
+}
+
+// JSON goes to `output`; CSV shares its name with a .csv extension unless it is the only format.
+// A "sqlite://" target ignores the format and inserts the records into the database instead.
+fn write_results(wifi_data: &[WifiData], json_data: &str, config: &Config, output: &str) -> Result<(), ScanError> {
+    if let Some(path) = sqlite_path(output) {
+        return write_database(wifi_data, path);
+    }
+    match config.output_format {
+        OutputFormat::Json => write_json_to_file(json_data, output),
+        OutputFormat::Csv => write_csv_to_file(wifi_data, output, config.append),
+        OutputFormat::Ndjson => write_ndjson_to_file(wifi_data, output, config.append),
+        OutputFormat::Wigle => write_wigle_to_file(wifi_data, output, config.append),
+        OutputFormat::Kml => write_kml_to_file(wifi_data, output),
+        OutputFormat::Both if output == STDOUT_TARGET => {
+            warn!("Both formats can't share stdout, writing only the JSON");
+            write_json_to_file(json_data, output)
+        }
+        OutputFormat::Both => {
+            write_json_to_file(json_data, output)?;
+            // out.json.gz pairs with out.csv.gz
+            let (stem, gzip) = match output.strip_suffix(GZIP_SUFFIX) {
+                Some(stem) => (stem, true),
+                None => (output, false),
+            };
+            let csv_output = Path::new(stem).with_extension("csv").to_string_lossy().into_owned();
+            write_csv_to_file(wifi_data, &compressed_name(&csv_output, gzip), false)
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn write_database(wifi_data: &[WifiData], path: &str) -> Result<(), ScanError> {
+    wifi_module::sqlite::write_sqlite(wifi_data, path)
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn write_database(_wifi_data: &[WifiData], path: &str) -> Result<(), ScanError> {
+    let message = format!("cannot write to {}, rebuild with --features sqlite", path);
+    Err(ScanError::Output(std::io::Error::new(std::io::ErrorKind::Unsupported, message)))
+}
+
+#[cfg(feature = "update-oui")]
+async fn run_update_oui(args: &Args) -> Result<(), ScanError> {
+    let path = args.oui.as_deref().unwrap_or(DEFAULT_OUI_PATH);
+    if let Some(days) = args.if_older_than {
+        if !is_stale(path, std::time::Duration::from_secs(days * 24 * 60 * 60)) {
+            info!("{} is less than {} days old, skipping the download", path, days);
+            return Ok(());
+        }
+    }
+    let assignments = update_oui(path).await?;
+    info!("Wrote {} assignments to {}", assignments, path);
+    Ok(())
+}
+
+#[cfg(not(feature = "update-oui"))]
+async fn run_update_oui(_args: &Args) -> Result<(), ScanError> {
+    let message = "update-oui is not available, rebuild with --features update-oui";
+    Err(ScanError::Output(std::io::Error::new(std::io::ErrorKind::Unsupported, message)))
+}
+
+// Prints the delta between the two results files of the compare subcommand
+fn compare_files(args: &Args) -> Result<(), ScanError> {
+    let (Some(previous), Some(current)) = (args.compare.as_deref(), args.compare_with.as_deref()) else {
+        return Ok(());
+    };
+    let (previous, current) = (read_results(previous)?, read_results(current)?);
+    let delta = ScanDelta::new(&previous, &current);
+    info!("{} added, {} removed, {} changed", delta.added.len(), delta.removed.len(), delta.changed.len());
+    println!("{}", to_json(&delta, true)?);
     Ok(())
 }
 
-fn read_oui_csv(filename: &str) -> Result<HashMap<String, String>, Box<dyn std::error::Error>> {
-    let mut oui_data = HashMap::new();
-    let mut rdr = csv::Reader::from_path(filename)?;
-    for result in rdr.records() {
-        let record = result?;
-        if record.len() >= 3 {
-            let mac_prefix = record.get(1).unwrap();
-            let manufacturer = record.get(2).unwrap();
-            oui_data.insert(mac_prefix.to_uppercase(), manufacturer.to_string());
-        }
-    }
-    Ok(oui_data)
-}
-
-async fn scan() -> Result<Vec<tokio_wifiscanner::Wifi>, tokio_wifiscanner::Error> {
-    tokio_wifiscanner::scan().await
-}
-
-// Helper function to generate the final results
-fn generate_results(
-    device_intervals: &HashMap<String, Vec<(Instant, Instant)>>,
-    networks: &[tokio_wifiscanner::Wifi],
-    oui_data: &HashMap<String, String>,
-) -> serde_json::Map<String, serde_json::Value> {
-    let mut results = serde_json::Map::new();
-    for (mac, intervals) in device_intervals {
-        let durations = intervals.iter()
-            .map(|(start, end)| {
-                // Ensure intervals are formatted from lower to higher time
-                let start_secs = start.elapsed().as_secs();
-                let end_secs = end.elapsed().as_secs();
-                if start_secs <= end_secs {
-                    format!("{}-{}", start_secs, end_secs)
-                } else {
-                    format!("{}-{}", end_secs, start_secs)
+// Stamps every record with the live gpsd position, falling back to the config's static one
+async fn locate(config: &Config, wifi_data: &mut [WifiData]) {
+    #[cfg(feature = "gpsd")]
+    let location = match &config.gpsd {
+        Some(addr) => match current_fix(addr).await {
+            Ok(location) => Some(location),
+            Err(e) => {
+                warn!("{}, falling back to the static position", e);
+                config.static_location()
+            }
+        },
+        None => config.static_location(),
+    };
+    #[cfg(not(feature = "gpsd"))]
+    let location = config.static_location();
+    for item in wifi_data {
+        item.location = location;
+    }
+}
+
+// --output, then the config's `output`, then a new wifi_{mode}_{timestamp} file in `output_dir`, then a
+// default file for the mode such as "wifi_instantdata.json". On a `schedule` every run gets its own
+// timestamped file in `output_dir`, like monitor mode.
+fn output_target(args: &Args, config: &Config, mode: &str) -> Result<String, ScanError> {
+    if config.schedule.is_some() {
+        let target = snapshot_target(args, config)?;
+        if target == STDOUT_TARGET || sqlite_path(&target).is_some() {
+            return Ok(target);
+        }
+        let output = Path::new(&target).join(timestamped_filename(mode, config.output_format.extension()));
+        return Ok(compressed_name(&output.to_string_lossy(), config.compress));
+    }
+    if let Some(target) = args.output.clone().or_else(|| config.output.clone()) {
+        return Ok(compressed_name(&target, config.compress));
+    }
+    let extension = config.output_format.extension();
+    let target = match &config.output_dir {
+        Some(dir) => {
+            std::fs::create_dir_all(dir).map_err(ScanError::Output)?;
+            Path::new(dir).join(timestamped_filename(mode, extension)).to_string_lossy().into_owned()
+        }
+        None => format!("wifi_{}data.{}", mode, extension),
+    };
+    Ok(compressed_name(&target, config.compress))
+}
+
+// Where monitor mode and a `schedule` put their many results: a "-" or "sqlite://" `output` as is, otherwise
+// the `output_dir` directory (the current one when unset), created if needed. Any other `output` names a single
+// file, as it does for one scan, so it's refused rather than turned into a directory.
+fn snapshot_target(args: &Args, config: &Config) -> Result<String, ScanError> {
+    match args.output.as_deref().or(config.output.as_deref()) {
+        Some(target) if target == STDOUT_TARGET || sqlite_path(target).is_some() => Ok(target.to_string()),
+        Some(target) => Err(ScanError::ConfigInvalid(format!(
+            "output \"{}\" is a single file, but monitor mode and a schedule write one per run; set output_dir (or --output-dir) instead",
+            target
+        ))),
+        None => {
+            let dir = config.output_dir.clone().unwrap_or_else(|| ".".to_string());
+            std::fs::create_dir_all(&dir).map_err(ScanError::Output)?;
+            Ok(dir)
+        }
+    }
+}
+
+// e.g. "wifi_monitor_20240101_120000.json", so repeated runs never overwrite each other
+fn timestamped_filename(mode: &str, extension: &str) -> String {
+    format!("wifi_{}_{}.{}", mode, Utc::now().format("%Y%m%d_%H%M%S"), extension)
+}
+
+// The results document, plus the "delta" against the --compare results when they were given
+fn once_document(config: &Config, previous: Option<&[WifiData]>, wifi_data: &[WifiData], summary: &Summary) -> Result<serde_json::Map<String, serde_json::Value>, ScanError> {
+    let mut document = results_document(wifi_data, summary, config.output_shape)?;
+    if let Some(previous) = previous {
+        let delta = ScanDelta::new(previous, wifi_data);
+        info!("{} added, {} removed, {} changed since the compared scan", delta.added.len(), delta.removed.len(), delta.changed.len());
+        document.insert("delta".to_string(), serde_json::to_value(&delta).map_err(ScanError::Serialize)?);
+    }
+    Ok(document)
+}
+
+// Reads the config, then scans the interfaces it names through a TimedScanner
+async fn run_wifi_script(args: &Args) -> Result<Outcome, ScanError> {
+    let config = read_config_with(&args.config, args.profile.as_deref(), |config| {
+        config.append |= args.append;
+        if args.interface.is_some() {
+            config.interface = args.interface.clone();
+        }
+        if let Some(scan_count) = args.scan_count {
+            config.scan_count = scan_count;
+        }
+        if args.output_dir.is_some() {
+            config.output_dir = args.output_dir.clone();
+        }
+    })?;
+    let interfaces = config.scan_interfaces();
+    for interface in &interfaces {
+        ensure_exists(interface).await?;
+    }
+    if !config.channels_to_hop.is_empty() && config.instant_scan && args.mode == Mode::Once {
+        warn!("An instant scan only visits the first of channels_to_hop, use a scheduled or monitor scan to cover them all");
+    }
+    if config.scan_count > 1 && !config.instant_scan && args.mode == Mode::Once {
+        warn!("scan_count only applies to instant and monitor scans, a scheduled scan polls on its own");
+    }
+    let scanner = TimedScanner::new(SystemScanner::new(interfaces).with_channel_hopping(&config.channels_to_hop));
+    scan_with(args, &config, &scanner).await
+}
+
+async fn scan_with(args: &Args, config: &Config, scanner: &TimedScanner<impl Scanner + Sync>) -> Result<Outcome, ScanError> {
+    #[cfg(not(feature = "gpsd"))]
+    if config.gpsd.is_some() {
+        warn!("A gpsd address is configured but this build lacks the gpsd feature, using the static position if any");
+    }
+
+    if args.mode == Mode::Monitor {
+        if args.dry_run || args.count {
+            warn!("--dry-run and --count only apply to scan, monitor mode still writes its snapshots");
+        }
+        if args.compare.is_some() {
+            warn!("--compare only applies to scan, ignoring it");
+        }
+        if config.schedule.is_some() {
+            warn!("schedule only applies to scan, monitor mode scans every monitor_interval_secs");
+        }
+        return run_monitor(args, config, scanner).await;
+    }
+    // Read up front so a bad --compare path fails before a long scheduled scan rather than after it
+    let previous = args.compare.as_deref().map(read_results).transpose()?;
+    let schedule = config.cron_schedule()?;
+    // Likewise an `output` that can't take a file per run
+    if schedule.is_some() && !args.count && !args.dry_run {
+        snapshot_target(args, config)?;
+    }
+    // Loaded and connected once, so a cron schedule's runs share the registry, the MQTT session and the
+    // metrics endpoint
+    let oui_data = load_oui(args, config);
+    let publishers = Publishers::connect(config);
+    let pass = Pass { args, config, scanner, oui_data: &oui_data, publishers: &publishers, previous: previous.as_deref() };
+    let outcome = match schedule {
+        Some(schedule) => run_on_schedule(&pass, &schedule).await,
+        None => {
+            let (stop_tx, stop) = tokio::sync::watch::channel(false);
+            // Only a scheduled scan has partial results to save; an instant one is left to the default Ctrl-C
+            if !config.instant_scan {
+                tokio::spawn(async move {
+                    ctrl_c_pressed().await;
+                    let _ = stop_tx.send(true);
+                });
+            }
+            run_pass(&pass, stop).await
+        }
+    };
+    publishers.close().await;
+    outcome
+}
+
+// What every instant or scheduled scan of a run shares
+struct Pass<'a, S> {
+    args: &'a Args,
+    config: &'a Config,
+    scanner: &'a TimedScanner<S>,
+    oui_data: &'a OuiDatabase,
+    publishers: &'a Publishers,
+    previous: Option<&'a [WifiData]>,
+}
+
+// Starts the scan at every time `schedule` names until Ctrl-C, staying resident in between. A run whose scan
+// fails is logged and skipped; a slot missed while a run was still going is skipped too.
+// Ctrl-C during an instant scan drops it, while a scheduled scan is left to save its partial results first.
+async fn run_on_schedule(pass: &Pass<'_, impl Scanner + Sync>, schedule: &cron::Schedule) -> Result<Outcome, ScanError> {
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let (stop_tx, stop) = tokio::sync::watch::channel(false);
+    let mut saved_any = false;
+    while let Some(next) = schedule.upcoming(Utc).next() {
+        info!("Next scan at {}", next.to_rfc3339_opts(SecondsFormat::Secs, true));
+        let wait = (next - Utc::now()).to_std().unwrap_or_default();
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = &mut ctrl_c => break,
+        }
+        let run = run_pass(pass, stop.clone());
+        tokio::pin!(run);
+        let mut stopping = false;
+        let result = tokio::select! {
+            result = &mut run => result,
+            _ = &mut ctrl_c => {
+                if pass.config.instant_scan {
+                    break;
                 }
-            })
-            .collect::<Vec<String>>().join(",");
+                // Cuts the countdown or the scan short, keeping what was gathered
+                let _ = stop_tx.send(true);
+                stopping = true;
+                run.await
+            }
+        };
+        match result {
+            Ok(outcome) => saved_any |= !matches!(outcome, Outcome::NoData | Outcome::TargetMissed),
+            // A scan failure may clear up by the next slot; config and output errors would only recur
+            Err(e @ (ScanError::Scan(_) | ScanError::Timeout(_))) => warn!("Scan failed, skipping this run: {}", e),
+            Err(e) => return Err(e),
+        }
+        if stopping {
+            break;
+        }
+    }
+    info!("Schedule stopped.");
+    Ok(if saved_any { Outcome::Saved } else { Outcome::NoData })
+}
 
-        let first_network = networks.iter().find(|n| n.mac == *mac).unwrap();
-        let manufacturer = get_manufacturer(&first_network.mac, oui_data).unwrap_or_else(|| "Unknown".to_string());
-        let sanitized_manufacturer = sanitize_string(&manufacturer);
+// One instant or scheduled scan, as `instant_scan` says, reported through `report`. A scheduled scan ends
+// early once `stop` turns true, and one stopped before it gathered anything writes nothing.
+async fn run_pass(pass: &Pass<'_, impl Scanner + Sync>, mut stop: tokio::sync::watch::Receiver<bool>) -> Result<Outcome, ScanError> {
+    let Pass { args, config, scanner, oui_data, publishers, previous } = *pass;
+    if config.instant_scan {
+        info!("Scan was set to be instant, starting scan...");
+        let started = Utc::now();
+        let mut wifi_data = scan_once(scanner, config, oui_data).await?;
+        locate(config, &mut wifi_data).await;
+        let summary = Summary::new(&wifi_data, started, Utc::now()).with_scan_samples(&scanner.take_samples());
+        publishers.publish("instant", &wifi_data).await;
 
-        let wifi_data_item = json!({
-            "ssid": sanitize_string(&first_network.ssid),
-            "mac": first_network.mac,
-            "manufacturer": sanitized_manufacturer,
-            "network_security": first_network.security,
-            "channel": first_network.channel,
-            "wifi_durations": durations
-        });
+        report(args, config, "instant", previous, &wifi_data, &summary)?;
+        Ok(Outcome::of(&wifi_data))
+    } else {
+        info!("Scan was set to be delayed");
+        let started = Utc::now();
+        let stopped = async {
+            let _ = stop.wait_for(|&stopped| stopped).await;
+        };
+        let (mut wifi_data, spotted) = scan_scheduled_watch(scanner, config, oui_data, stopped).await?;
+        if wifi_data.is_empty() && *stop.borrow() {
+            return Ok(Outcome::NoData);
+        }
+        locate(config, &mut wifi_data).await;
+        let summary = Summary::new(&wifi_data, started, Utc::now()).with_scan_samples(&scanner.take_samples());
+        publishers.publish("scheduled", &wifi_data).await;
 
-        results.insert(mac.clone(), wifi_data_item);
+        report(args, config, "scheduled", previous, &wifi_data, &summary)?;
+        Ok(match (config.has_watch_target(), spotted) {
+            (true, true) => Outcome::TargetSpotted,
+            (true, false) => Outcome::TargetMissed,
+            (false, _) => Outcome::of(&wifi_data),
+        })
     }
-    results
 }
 
+// Prints the --count headcount, or else prints the results document and writes it to the output target
+// unless this is a --dry-run
+fn report(args: &Args, config: &Config, mode: &str, previous: Option<&[WifiData]>, wifi_data: &[WifiData], summary: &Summary) -> Result<(), ScanError> {
+    if args.count {
+        println!("{}", distinct_devices(wifi_data, args.skip_randomized));
+        return Ok(());
+    }
+    let json_data = to_json(&once_document(config, previous, wifi_data, summary)?, config.pretty)?;
+    if args.dry_run {
+        println!("{}", json_data);
+        return Ok(());
+    }
+    let output = output_target(args, config, mode)?;
+    // A "-" target already writes the results to stdout, in whichever format was asked for
+    if output != STDOUT_TARGET {
+        println!("{}", json_data);
+    }
+    write_results(wifi_data, &json_data, config, &output)
+}
+
+// Distinct BSSIDs in the results, leaving out locally administered ones when asked
+fn distinct_devices(wifi_data: &[WifiData], skip_randomized: bool) -> usize {
+    wifi_data.iter()
+        .filter(|item| !(skip_randomized && item.randomized))
+        .map(|item| item.mac.as_str())
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+// Repeats the instant scan every `monitor_interval_secs`, one snapshot file per cycle, until Ctrl-C.
+// NDJSON instead streams every cycle into a single file so it can be followed with `tail -f`, or to stdout for "-".
+async fn run_monitor(args: &Args, config: &Config, scanner: &TimedScanner<impl Scanner + Sync>) -> Result<Outcome, ScanError> {
+    info!("Monitor mode, scanning every {} seconds until Ctrl-C...", config.monitor_interval_secs);
+    let target = snapshot_target(args, config)?;
+    let oui_data = load_oui(args, config);
+    let output_dir = Path::new(&target);
+    let extension = config.output_format.extension();
+    let mut stream: Option<Box<dyn Write>> = None;
+    if target == STDOUT_TARGET {
+        if config.output_format != OutputFormat::Ndjson {
+            let message = "monitor mode can only stream ndjson to stdout";
+            return Err(ScanError::Output(std::io::Error::new(std::io::ErrorKind::InvalidInput, message)));
+        }
+        stream = Some(Box::new(std::io::stdout()));
+    } else if config.output_format == OutputFormat::Ndjson && sqlite_path(&target).is_none() {
+        let output = compressed_name(&output_dir.join(timestamped_filename("monitor", extension)).to_string_lossy(), config.compress);
+        // Flushed after every cycle; a compressed stream gets its gzip trailer when it is dropped on exit
+        let file = create_output(&output, false).map_err(ScanError::Output)?;
+        info!("Streaming results to {}", output);
+        stream = Some(Box::new(file));
+    }
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
+    let publishers = Publishers::connect(config);
+    let mut presence = PresenceTracker::new();
+    let mut saved_any = false;
+    loop {
+        let started = Utc::now();
+        // Dropped on cycles that don't write a summary, so they can't pile up
+        scanner.take_samples();
+        tokio::select! {
+            result = scan_once(scanner, config, &oui_data) => match result {
+                Ok(mut wifi_data) => {
+                    locate(config, &mut wifi_data).await;
+                    if let Some(writer) = stream.as_mut() {
+                        write_ndjson(&wifi_data, writer)?;
+                        info!("Appended {} networks", wifi_data.len());
+                    } else if let Some(path) = sqlite_path(&target) {
+                        write_database(&wifi_data, path)?;
+                        info!("Recorded {} networks in {}", wifi_data.len(), path);
+                    } else {
+                        let summary = Summary::new(&wifi_data, started, Utc::now()).with_scan_samples(&scanner.take_samples());
+                        let json_data = to_json(&results_document(&wifi_data, &summary, config.output_shape)?, config.pretty)?;
+                        let output = compressed_name(&output_dir.join(timestamped_filename("monitor", extension)).to_string_lossy(), config.compress);
+                        write_results(&wifi_data, &json_data, config, &output)?;
+                        info!("Saved {} networks to {}", wifi_data.len(), output);
+                    }
+                    publishers.publish("monitor", &wifi_data).await;
+                    if config.report_departures {
+                        let departures = presence.update(&wifi_data, config.absence_threshold_secs);
+                        publishers.publish_departures(&departures, stream.as_deref_mut()).await?;
+                    }
+                    saved_any |= !wifi_data.is_empty();
+                }
+                Err(e) => warn!("Scan failed, skipping this cycle: {}", e),
+            },
+            _ = &mut ctrl_c => break,
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(tokio::time::Duration::from_secs(config.monitor_interval_secs)) => {}
+            _ = &mut ctrl_c => break,
+        }
+    }
+    publishers.close().await;
+    info!("Monitor stopped.");
+    Ok(if saved_any { Outcome::Saved } else { Outcome::NoData })
+}
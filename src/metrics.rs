@@ -0,0 +1,94 @@
+use crate::{Security, WifiData};
+use axum::routing::get;
+use axum::Router;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use tracing::error;
+
+// Upper bounds (dBm) of the signal histogram buckets; +Inf is implied
+const SIGNAL_BUCKETS: [i32; 7] = [-90, -80, -70, -60, -50, -40, -30];
+
+#[derive(Default)]
+struct State {
+    scans_total: u64,
+    networks: usize,
+    open_networks: usize,
+    networks_by_band: BTreeMap<String, usize>,
+    signal_bucket_counts: [u64; SIGNAL_BUCKETS.len()],
+    signal_sum: f64,
+    signal_count: u64,
+}
+
+// Gauges describe the latest scan; the signal histogram and scan counter accumulate over the process lifetime
+#[derive(Clone, Default)]
+pub struct Metrics(Arc<Mutex<State>>);
+
+impl Metrics {
+    pub fn record_scan(&self, data: &[WifiData]) {
+        let mut state = self.0.lock().unwrap();
+        state.scans_total += 1;
+        state.networks = data.len();
+        state.open_networks = data.iter().filter(|item| item.security == Security::Open).count();
+        state.networks_by_band.clear();
+        for item in data {
+            *state.networks_by_band.entry(item.band.clone()).or_default() += 1;
+            if let Some(dbm) = item.signal_dbm {
+                for (i, bound) in SIGNAL_BUCKETS.iter().enumerate() {
+                    if dbm <= *bound {
+                        state.signal_bucket_counts[i] += 1;
+                    }
+                }
+                state.signal_sum += dbm as f64;
+                state.signal_count += 1;
+            }
+        }
+    }
+
+    // Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let state = self.0.lock().unwrap();
+        let mut out = String::new();
+        let _ = writeln!(out, "# HELP wifi_scans_total Completed scan cycles.");
+        let _ = writeln!(out, "# TYPE wifi_scans_total counter");
+        let _ = writeln!(out, "wifi_scans_total {}", state.scans_total);
+        let _ = writeln!(out, "# HELP wifi_networks_total Networks seen in the latest scan.");
+        let _ = writeln!(out, "# TYPE wifi_networks_total gauge");
+        let _ = writeln!(out, "wifi_networks_total {}", state.networks);
+        let _ = writeln!(out, "# HELP wifi_open_networks_total Open networks seen in the latest scan.");
+        let _ = writeln!(out, "# TYPE wifi_open_networks_total gauge");
+        let _ = writeln!(out, "wifi_open_networks_total {}", state.open_networks);
+        let _ = writeln!(out, "# HELP wifi_band_networks_total Networks per band in the latest scan.");
+        let _ = writeln!(out, "# TYPE wifi_band_networks_total gauge");
+        for (band, count) in &state.networks_by_band {
+            let _ = writeln!(out, "wifi_band_networks_total{{band=\"{}\"}} {}", band, count);
+        }
+        let _ = writeln!(out, "# HELP wifi_signal_dbm Signal strength of every sighting.");
+        let _ = writeln!(out, "# TYPE wifi_signal_dbm histogram");
+        for (bound, count) in SIGNAL_BUCKETS.iter().zip(state.signal_bucket_counts) {
+            let _ = writeln!(out, "wifi_signal_dbm_bucket{{le=\"{}\"}} {}", bound, count);
+        }
+        let _ = writeln!(out, "wifi_signal_dbm_bucket{{le=\"+Inf\"}} {}", state.signal_count);
+        let _ = writeln!(out, "wifi_signal_dbm_sum {}", state.signal_sum);
+        let _ = writeln!(out, "wifi_signal_dbm_count {}", state.signal_count);
+        out
+    }
+
+    // Serves GET /metrics in the background; a failed bind is reported and metrics stay disabled
+    pub fn serve(&self, port: u16) {
+        let metrics = self.clone();
+        tokio::spawn(async move {
+            let app = Router::new().route("/metrics", get(move || async move { metrics.render() }));
+            let listener = match tokio::net::TcpListener::bind(("0.0.0.0", port)).await {
+                Ok(listener) => listener,
+                Err(e) => {
+                    error!("Could not start metrics endpoint on port {}: {}", port, e);
+                    return;
+                }
+            };
+            if let Err(e) = axum::serve(listener, app).await {
+                error!("Metrics endpoint stopped: {}", e);
+            }
+        });
+    }
+}
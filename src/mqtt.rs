@@ -0,0 +1,68 @@
+use crate::{Departure, MqttConfig, ScanError, WifiData};
+use rumqttc::{AsyncClient, Event, MqttOptions, Outgoing, QoS};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+// Publishes records as JSON to `<topic>/<mac>`. The event loop runs on its own task and keeps
+// reconnecting while the broker is unreachable; publishes made meanwhile queue up on the client.
+pub struct MqttPublisher {
+    client: AsyncClient,
+    topic: String,
+    event_loop: JoinHandle<()>,
+}
+
+impl MqttPublisher {
+    pub fn connect(config: &MqttConfig) -> MqttPublisher {
+        let client_id = format!("wifi_module-{}", std::process::id());
+        let mut options = MqttOptions::new(client_id, &config.broker, config.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        if let Some(username) = &config.username {
+            options.set_credentials(username, config.password.as_deref().unwrap_or(""));
+        }
+
+        let (client, mut event_loop) = AsyncClient::new(options, 100);
+        let event_loop = tokio::spawn(async move {
+            loop {
+                match event_loop.poll().await {
+                    Ok(Event::Outgoing(Outgoing::Disconnect)) => break,
+                    Ok(_) => {}
+                    Err(e) => {
+                        warn!("MQTT connection error: {}, reconnecting in {}s", e, RECONNECT_DELAY.as_secs());
+                        tokio::time::sleep(RECONNECT_DELAY).await;
+                    }
+                }
+            }
+        });
+
+        MqttPublisher { client, topic: config.topic.trim_end_matches('/').to_string(), event_loop }
+    }
+
+    pub async fn publish(&self, data: &[WifiData]) -> Result<(), ScanError> {
+        for item in data {
+            let payload = serde_json::to_vec(item).map_err(ScanError::Serialize)?;
+            let topic = format!("{}/{}", self.topic, item.mac);
+            self.client.publish(topic, QoS::AtLeastOnce, false, payload).await.map_err(ScanError::Mqtt)?;
+        }
+        Ok(())
+    }
+
+    // One message per device under `<topic>/<mac>/departed`
+    pub async fn publish_departures(&self, departures: &[Departure]) -> Result<(), ScanError> {
+        for departure in departures {
+            let payload = serde_json::to_vec(departure).map_err(ScanError::Serialize)?;
+            let topic = format!("{}/{}/departed", self.topic, departure.mac);
+            self.client.publish(topic, QoS::AtLeastOnce, false, payload).await.map_err(ScanError::Mqtt)?;
+        }
+        Ok(())
+    }
+
+    // Sends whatever is still queued, then disconnects; gives up after `timeout` if the broker is gone
+    pub async fn close(self, timeout: Duration) {
+        if self.client.disconnect().await.is_ok() {
+            let _ = tokio::time::timeout(timeout, self.event_loop).await;
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use crate::{is_locally_administered, normalize_mac, ScanError};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+#[cfg(feature = "embedded-oui")]
+const EMBEDDED_OUI_CSV: &str = include_str!("database/oui.csv");
+
+// Manufacturer names by MAC prefix, 6, 7 or 9 hex digits long, plus any custom labels keyed by full
+// normalized MAC
+//
+// Parse it once with `OuiDatabase::load_from_csv` or `load_embedded` and pass it by reference; clones
+// share the same data, so it can be handed to other async tasks cheaply. The bundled registry (~34.5k MA-L
+// rows, ~69k trie nodes) takes roughly 6 MB. `OuiDatabase::default()` is empty, so every manufacturer
+// reports as Unknown.
+#[derive(Clone, Default)]
+pub struct OuiDatabase {
+    prefixes: Arc<ManufacturerTrie>,
+    labels: Arc<HashMap<String, String>>,
+}
+
+// Names keyed by hex prefixes of any length, one trie level per nibble. A lookup walks the MAC's digits
+// once and keeps the deepest name it passes, so MA-S (36 bits) beats MA-M (28) beats MA-L (24).
+pub struct ManufacturerTrie {
+    // nodes[0] is the root, which is never anyone's child, so a child index of 0 means "none"
+    nodes: Vec<TrieNode>,
+    names: Vec<String>,
+}
+
+#[derive(Default)]
+struct TrieNode {
+    children: [u32; 16],
+    name: Option<u32>,
+}
+
+impl Default for ManufacturerTrie {
+    fn default() -> Self {
+        ManufacturerTrie { nodes: vec![TrieNode::default()], names: Vec::new() }
+    }
+}
+
+impl ManufacturerTrie {
+    pub fn new() -> Self {
+        ManufacturerTrie::default()
+    }
+
+    // `prefix_hex` is read as hex digits, 4 bits each; separators and other characters are skipped,
+    // so "AC-DE-48" and "acde48" are the same prefix. Inserting a prefix again replaces its name.
+    pub fn insert(&mut self, prefix_hex: &str, name: &str) {
+        let mut node = 0;
+        for nibble in prefix_hex.chars().filter_map(|c| c.to_digit(16)) {
+            let child = self.nodes[node].children[nibble as usize];
+            node = if child == 0 {
+                self.nodes.push(TrieNode::default());
+                let created = self.nodes.len() - 1;
+                self.nodes[node].children[nibble as usize] = created as u32;
+                created
+            } else {
+                child as usize
+            };
+        }
+        match self.nodes[node].name {
+            Some(index) => self.names[index as usize] = name.to_string(),
+            None => {
+                self.nodes[node].name = Some(self.names.len() as u32);
+                self.names.push(name.to_string());
+            }
+        }
+    }
+
+    // Name of the longest inserted prefix of `mac`, in any separator style
+    pub fn lookup(&self, mac: &str) -> Option<&str> {
+        let mut node = 0;
+        let mut found = None;
+        for nibble in mac.chars().filter_map(|c| c.to_digit(16)) {
+            match self.nodes[node].children[nibble as usize] {
+                0 => break,
+                child => node = child as usize,
+            }
+            found = self.nodes[node].name.or(found);
+        }
+        found.map(|index| self.names[index as usize].as_str())
+    }
+
+    // Number of distinct prefixes
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+// Number of hex digits an assignment identifies, from the registry column of the IEEE CSV
+fn prefix_nibbles(registry: &str, assignment: &str) -> usize {
+    match registry {
+        "MA-L" => 6,
+        "MA-M" => 7,
+        "MA-S" | "IAB" => 9,
+        _ => assignment.len(),
+    }
+}
+
+// Uppercase hex digits of an assignment, so "ac-de-48", "AC:DE:48" and "ACDE48" share one key
+fn oui_key(assignment: &str) -> String {
+    assignment.trim().chars().filter(|c| !matches!(c, '-' | ':' | '.')).collect::<String>().to_uppercase()
+}
+
+impl OuiDatabase {
+    pub fn load_from_csv(filename: &str) -> Result<Self, ScanError> {
+        let file = File::open(filename).map_err(ScanError::OuiRead)?;
+        OuiDatabase::from_reader(file)
+    }
+
+    // Parses the copy of the registry compiled into the binary
+    #[cfg(feature = "embedded-oui")]
+    pub fn load_embedded() -> Result<Self, ScanError> {
+        OuiDatabase::from_reader(EMBEDDED_OUI_CSV.as_bytes())
+    }
+
+    // Shared by the file and embedded loaders, and usable with an in-memory CSV.
+    // Columns are found by their IEEE header names, so extra or reordered columns are fine.
+    // Rows that can't be read or lack an assignment or name are skipped and counted.
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, ScanError> {
+        let mut oui_data = ManufacturerTrie::new();
+        let mut rdr = csv::ReaderBuilder::new().flexible(true).from_reader(reader);
+        let headers = rdr.headers().map_err(ScanError::OuiParse)?.clone();
+        let column = |name: &str| headers.iter().position(|header| header.trim().eq_ignore_ascii_case(name));
+        let registry_column = column("Registry");
+        let (Some(assignment_column), Some(name_column)) = (column("Assignment"), column("Organization Name")) else {
+            return Err(ScanError::OuiFormat("expected \"Assignment\" and \"Organization Name\" columns".to_string()));
+        };
+        let mut skipped = 0;
+        for result in rdr.records() {
+            let Ok(record) = result else {
+                skipped += 1;
+                continue;
+            };
+            let mac_prefix = oui_key(record.get(assignment_column).unwrap_or(""));
+            let manufacturer = record.get(name_column).unwrap_or("").trim();
+            if mac_prefix.is_empty() || manufacturer.is_empty() {
+                skipped += 1;
+                continue;
+            }
+            let registry = registry_column.and_then(|column| record.get(column)).unwrap_or("");
+            let nibbles = prefix_nibbles(registry, &mac_prefix);
+            let key = mac_prefix.get(..nibbles).unwrap_or(&mac_prefix);
+            oui_data.insert(key, manufacturer);
+        }
+        if skipped > 0 {
+            warn!("Skipped {} malformed rows in the OUI database", skipped);
+        }
+        Ok(OuiDatabase { prefixes: Arc::new(oui_data), labels: Arc::default() })
+    }
+
+    // Adds the "mac,label" rows of `filename`; a header row is optional
+    pub fn with_custom_labels(self, filename: &str) -> Result<Self, ScanError> {
+        let file = File::open(filename).map_err(ScanError::LabelsRead)?;
+        let mut rdr = csv::ReaderBuilder::new().has_headers(false).flexible(true).from_reader(file);
+        let mut labels = HashMap::new();
+        for result in rdr.records() {
+            let record = result.map_err(ScanError::LabelsParse)?;
+            let (Some(mac), Some(label)) = (record.get(0), record.get(1)) else {
+                continue;
+            };
+            if mac.trim().eq_ignore_ascii_case("mac") {
+                continue;
+            }
+            labels.insert(normalize_mac(mac), label.trim().to_string());
+        }
+        debug!("Loaded {} custom labels from {}", labels.len(), filename);
+        Ok(OuiDatabase { labels: Arc::new(labels), ..self })
+    }
+
+    // An exact custom label first, then the registry name. None when nothing matches.
+    pub fn lookup(&self, mac: &str) -> Option<&str> {
+        self.label(mac).or_else(|| self.registry_name(mac))
+    }
+
+    // The custom label for exactly this MAC, randomized or not
+    pub fn label(&self, mac: &str) -> Option<&str> {
+        self.labels.get(&normalize_mac(mac)).map(String::as_str)
+    }
+
+    // Longest registry prefix, so MA-M/MA-S blocks inside a shared OUI resolve to the small vendor.
+    // Randomized MACs never match.
+    pub fn registry_name(&self, mac: &str) -> Option<&str> {
+        let mac = normalize_mac(mac);
+        if is_locally_administered(&mac) {
+            return None;
+        }
+        self.prefixes.lookup(&mac)
+    }
+
+    // Number of registry prefixes; custom labels aren't counted
+    pub fn len(&self) -> usize {
+        self.prefixes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prefixes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "Registry,Assignment,Organization Name,Organization Address\n";
+
+    fn registry(rows: &str) -> OuiDatabase {
+        OuiDatabase::from_reader(format!("{}{}", HEADER, rows).as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn ieee_assignments_with_separators_resolve() {
+        let oui = registry("MA-L,AC-DE-48,PRIVATE,\n\
+                            MA-L,00:1B:63,\"Apple, Inc.\",1 Infinite Loop Cupertino CA US 95014\n\
+                            MA-M,70-B3-D5-1,Example MA-M Vendor,\n");
+        assert_eq!(oui.lookup("ac:de:48:00:11:22"), Some("PRIVATE"));
+        assert_eq!(oui.lookup("00-1b-63-84-45-e6"), Some("Apple, Inc."));
+        assert_eq!(oui.lookup("001b.6384.45e6"), Some("Apple, Inc."));
+        assert_eq!(oui.lookup("70:B3:D5:1F:00:01"), Some("Example MA-M Vendor"));
+        assert_eq!(oui.lookup("70:B3:D5:2F:00:01"), None);
+    }
+
+    #[test]
+    fn longest_prefix_wins_across_block_sizes() {
+        // MA-L, MA-M and MA-S blocks nested in one OUI, an IAB, and a registry this code doesn't
+        // know, whose assignment is taken at its full length
+        let oui = registry("MA-L,70B3D5,IEEE Registration Authority,\n\
+                            MA-M,70B3D51,Example MA-M Vendor,\n\
+                            MA-S,70B3D51F2,Example MA-S Vendor,\n\
+                            IAB,0050C2ABC,Example IAB Vendor,\n\
+                            CID,0A1B2C3D,Company ID,\n");
+        assert_eq!(oui.len(), 5);
+        assert_eq!(oui.lookup("70:B3:D5:00:00:01"), Some("IEEE Registration Authority"));
+        assert_eq!(oui.lookup("70:B3:D5:10:00:01"), Some("Example MA-M Vendor"));
+        assert_eq!(oui.lookup("70:B3:D5:1F:20:01"), Some("Example MA-S Vendor"));
+        assert_eq!(oui.lookup("70:B3:D5:1F:30:01"), Some("Example MA-M Vendor"));
+        assert_eq!(oui.lookup("00:50:C2:AB:C0:01"), Some("Example IAB Vendor"));
+        assert_eq!(oui.lookup("00:50:C2:AB:D0:01"), None);
+        // Only the full 8 digits match, and the locally administered bit hides it from a bare prefix lookup
+        assert_eq!(oui.prefixes.lookup("0A:1B:2C:3D:00:01"), Some("Company ID"));
+        assert_eq!(oui.lookup("0A:1B:2C:3D:00:01"), None);
+    }
+
+    #[test]
+    fn assignments_longer_than_their_block_are_cut_to_it() {
+        let oui = registry("MA-L,001B63AA,\"Apple, Inc.\",\n");
+        assert_eq!(oui.lookup("00:1B:63:00:00:01"), Some("Apple, Inc."));
+    }
+
+    #[test]
+    fn malformed_rows_are_skipped() {
+        let oui = registry("MA-L,001B63,\"Apple, Inc.\",\n\
+                            MA-L,,Missing Assignment,\n\
+                            MA-L,10E992,,\n\
+                            MA-L,10E993\n\
+                            MA-L,\"AC-DE-48,an unterminated quote\n");
+        assert_eq!(oui.len(), 1);
+        assert_eq!(oui.lookup("00:1B:63:84:45:E6"), Some("Apple, Inc."));
+        assert_eq!(oui.lookup("10:E9:92:00:00:01"), None);
+    }
+
+    #[test]
+    fn registry_without_the_ieee_columns_is_rejected() {
+        let result = OuiDatabase::from_reader("Prefix,Vendor\n001B63,Apple\n".as_bytes());
+        assert!(matches!(result, Err(ScanError::OuiFormat(_))));
+        // Columns are found by name, so reordered and extra ones are fine
+        let reordered = OuiDatabase::from_reader("Organization Name,Notes,Assignment\nApple,x,001B63\n".as_bytes()).unwrap();
+        assert_eq!(reordered.lookup("00:1B:63:84:45:E6"), Some("Apple"));
+    }
+
+    #[test]
+    fn custom_labels_win_and_are_the_only_match_for_randomized_macs() {
+        let path = std::env::temp_dir().join(format!("wifi_module_labels_{}.csv", std::process::id()));
+        std::fs::write(&path, "mac,label\n00-1b-63-84-45-e6,Front desk\n02:00:00:00:00:01,Test phone\n").unwrap();
+        let oui = registry("MA-L,001B63,\"Apple, Inc.\",\n").with_custom_labels(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(oui.lookup("00:1B:63:84:45:E6"), Some("Front desk"));
+        assert_eq!(oui.registry_name("00:1B:63:84:45:E6"), Some("Apple, Inc."));
+        assert_eq!(oui.label("00:1B:63:84:45:E7"), None);
+        assert_eq!(oui.lookup("00:1B:63:84:45:E7"), Some("Apple, Inc."));
+        assert_eq!(oui.lookup("02:00:00:00:00:01"), Some("Test phone"));
+        assert_eq!(oui.lookup("02:00:00:00:00:02"), None);
+        assert_eq!(oui.len(), 1);
+    }
+}
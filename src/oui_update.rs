@@ -0,0 +1,97 @@
+use crate::{OuiDatabase, ScanError};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+// MA-L, MA-M and MA-S assignments, all in the Registry,Assignment,Organization Name,Organization Address layout
+const REGISTRY_URLS: [&str; 3] = [
+    "https://standards-oui.ieee.org/oui/oui.csv",
+    "https://standards-oui.ieee.org/oui28/mam.csv",
+    "https://standards-oui.ieee.org/oui36/oui36.csv",
+];
+
+// True when `path` is missing, or was last written more than `max_age` ago
+pub fn is_stale(path: &str, max_age: Duration) -> bool {
+    match std::fs::metadata(path).and_then(|metadata| metadata.modified()) {
+        Ok(modified) => SystemTime::now().duration_since(modified).is_ok_and(|age| age > max_age),
+        Err(_) => true,
+    }
+}
+
+// Downloads every registry into one CSV at `path` and returns the number of assignments written.
+// The old file is only replaced once the new one has loaded cleanly.
+pub async fn update_oui(path: &str) -> Result<usize, ScanError> {
+    let client = reqwest::Client::builder()
+        .user_agent(concat!("wifi_module/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .map_err(ScanError::OuiDownload)?;
+    let mut wtr = csv::Writer::from_writer(Vec::new());
+    wtr.write_record(["Registry", "Assignment", "Organization Name", "Organization Address"])
+        .map_err(ScanError::OuiParse)?;
+    let mut assignments = 0;
+    for url in REGISTRY_URLS {
+        info!("Downloading {}", url);
+        let body = client.get(url).send().await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(ScanError::OuiDownload)?
+            .text().await
+            .map_err(ScanError::OuiDownload)?;
+        assignments += copy_registry(body.as_bytes(), &mut wtr)?;
+    }
+    let csv_data = wtr.into_inner().map_err(|e| ScanError::Output(e.into_error()))?;
+    OuiDatabase::from_reader(csv_data.as_slice())?;
+
+    let partial = format!("{}.partial", path);
+    std::fs::write(&partial, &csv_data).map_err(ScanError::Output)?;
+    std::fs::rename(&partial, path).map_err(ScanError::Output)?;
+    Ok(assignments)
+}
+
+// Appends one downloaded registry's rows to `wtr` in the combined layout and returns how many it wrote.
+// Columns are found by their header names like `OuiDatabase::from_reader` does, and rows without an
+// assignment or organization name are skipped.
+fn copy_registry<W: std::io::Write>(registry: &[u8], wtr: &mut csv::Writer<W>) -> Result<usize, ScanError> {
+    let mut rdr = csv::ReaderBuilder::new().flexible(true).from_reader(registry);
+    let headers = rdr.headers().map_err(ScanError::OuiParse)?.clone();
+    let column = |name: &str| headers.iter().position(|header| header.trim().eq_ignore_ascii_case(name));
+    let (registry_column, address_column) = (column("Registry"), column("Organization Address"));
+    let (Some(assignment_column), Some(name_column)) = (column("Assignment"), column("Organization Name")) else {
+        return Err(ScanError::OuiFormat("expected \"Assignment\" and \"Organization Name\" columns".to_string()));
+    };
+    let mut written = 0;
+    let mut skipped = 0;
+    for result in rdr.records() {
+        let record = result.map_err(ScanError::OuiParse)?;
+        let field = |column: Option<usize>| column.and_then(|column| record.get(column)).unwrap_or("");
+        let (assignment, name) = (record.get(assignment_column).unwrap_or(""), record.get(name_column).unwrap_or(""));
+        if assignment.trim().is_empty() || name.trim().is_empty() {
+            skipped += 1;
+            continue;
+        }
+        wtr.write_record([field(registry_column), assignment, name, field(address_column)]).map_err(ScanError::OuiParse)?;
+        written += 1;
+    }
+    if skipped > 0 {
+        warn!("Skipped {} rows without an assignment or organization name", skipped);
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_columns_are_found_by_name() {
+        let registry = "Organization Name,Assignment,Registry\n\
+                        \"Apple, Inc.\",001B63,MA-L\n\
+                        Short Row\n\
+                        ,10E992,MA-L\n";
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        assert_eq!(copy_registry(registry.as_bytes(), &mut wtr).unwrap(), 1);
+        let written = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        assert_eq!(written, "MA-L,001B63,\"Apple, Inc.\",\n");
+
+        let unlabelled = copy_registry("a,b,c\n1,2,3\n".as_bytes(), &mut csv::Writer::from_writer(Vec::new()));
+        assert!(matches!(unlabelled, Err(ScanError::OuiFormat(_))));
+    }
+}
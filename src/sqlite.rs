@@ -0,0 +1,57 @@
+use crate::{ScanError, WifiData};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+
+const CREATE_SIGHTINGS: &str = "CREATE TABLE IF NOT EXISTS sightings (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    scan_id INTEGER NOT NULL,
+    mac TEXT NOT NULL,
+    ssid TEXT NOT NULL,
+    manufacturer TEXT,
+    channel INTEGER NOT NULL,
+    security TEXT NOT NULL,
+    signal INTEGER,
+    first_seen TEXT,
+    last_seen TEXT
+)";
+
+// Appends one row per record; every record from the same call shares a new scan_id.
+// Timestamps are stored as "YYYY-MM-DD HH:MM:SS+00:00" text, which SQLite's date functions understand.
+// They're formatted here since rusqlite's own chrono conversion adds fractional seconds.
+pub fn write_sqlite(data: &[WifiData], path: &str) -> Result<(), ScanError> {
+    let mut conn = Connection::open(path).map_err(ScanError::Database)?;
+    conn.execute(CREATE_SIGHTINGS, []).map_err(ScanError::Database)?;
+
+    let tx = conn.transaction().map_err(ScanError::Database)?;
+    let scan_id: i64 = tx
+        .query_row("SELECT COALESCE(MAX(scan_id), 0) + 1 FROM sightings", [], |row| row.get(0))
+        .map_err(ScanError::Database)?;
+    {
+        let mut insert = tx
+            .prepare(
+                "INSERT INTO sightings (scan_id, mac, ssid, manufacturer, channel, security, signal, first_seen, last_seen)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            )
+            .map_err(ScanError::Database)?;
+        for item in data {
+            insert
+                .execute(params![
+                    scan_id,
+                    item.mac,
+                    item.ssid,
+                    item.manufacturer,
+                    item.channel,
+                    item.security.as_str(),
+                    item.signal_dbm,
+                    item.first_seen.map(sqlite_timestamp),
+                    item.last_seen.map(sqlite_timestamp),
+                ])
+                .map_err(ScanError::Database)?;
+        }
+    }
+    tx.commit().map_err(ScanError::Database)
+}
+
+fn sqlite_timestamp(at: DateTime<Utc>) -> String {
+    at.format("%Y-%m-%d %H:%M:%S+00:00").to_string()
+}
@@ -0,0 +1,60 @@
+use crate::{Departure, ScanError, WifiData};
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use std::time::Duration;
+use tracing::warn;
+
+#[derive(Serialize)]
+struct Envelope<'a, T> {
+    mode: &'a str,
+    timestamp: String,
+    results: &'a [T],
+}
+
+// POSTs each result set as `{ "mode", "timestamp", "results" }` to a fixed URL
+pub struct WebhookClient {
+    client: reqwest::Client,
+    url: String,
+    attempts: u32,
+}
+
+impl WebhookClient {
+    pub fn new(url: &str, timeout: Duration, attempts: u32) -> Result<WebhookClient, ScanError> {
+        let client = reqwest::Client::builder().timeout(timeout).build().map_err(ScanError::Webhook)?;
+        Ok(WebhookClient { client, url: url.to_string(), attempts })
+    }
+
+    // Transport errors and non-2xx responses are retried, doubling the wait from 500ms each time
+    pub async fn post(&self, mode: &str, data: &[WifiData]) -> Result<(), ScanError> {
+        self.post_envelope(mode, data).await
+    }
+
+    // Sent with mode "departed"
+    pub async fn post_departures(&self, departures: &[Departure]) -> Result<(), ScanError> {
+        self.post_envelope("departed", departures).await
+    }
+
+    async fn post_envelope<T: Serialize>(&self, mode: &str, data: &[T]) -> Result<(), ScanError> {
+        let envelope = Envelope {
+            mode,
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            results: data,
+        };
+        let mut backoff = Duration::from_millis(500);
+        let mut attempt = 1;
+        loop {
+            let result = self.client.post(&self.url).json(&envelope).send().await
+                .and_then(reqwest::Response::error_for_status);
+            match result {
+                Ok(_) => return Ok(()),
+                Err(e) if attempt < self.attempts => {
+                    warn!("Webhook attempt {} of {} failed: {}, retrying in {}ms", attempt, self.attempts, e, backoff.as_millis());
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                Err(e) => return Err(ScanError::Webhook(e)),
+            }
+        }
+    }
+}
@@ -0,0 +1,219 @@
+// Enrichment of a fixed network list against a small in-memory OUI registry
+use tokio_wifiscanner::Wifi;
+use chrono::Utc;
+use std::io::Read;
+use wifi_module::{
+    compressed_name, convert_to_wifi_data, read_results, results_document, results_schema, write_json_to_file, write_ndjson_to_file,
+    Config, OuiDatabase, OutputShape, ScanDelta, Security, Summary, WifiData,
+};
+
+const REGISTRY: &str = "Registry,Assignment,Organization Name,Organization Address\n\
+                        MA-L,001B63,\"Apple, Inc.\",1 Infinite Loop Cupertino CA US 95014\n\
+                        MA-L,10E992,Example Vendor,\n";
+
+fn network(mac: &str, ssid: &str, channel: &str, security: &str) -> Wifi {
+    Wifi {
+        mac: mac.to_string(),
+        ssid: ssid.to_string(),
+        channel: channel.to_string(),
+        signal_level: "-61".to_string(),
+        security: security.to_string(),
+    }
+}
+
+#[test]
+fn convert_to_wifi_data_enriches_every_network() {
+    let oui = OuiDatabase::from_reader(REGISTRY.as_bytes()).unwrap();
+    let config: Config = serde_json::from_str("{}").unwrap();
+    let networks = [
+        network("00-1b-63-84-45-e6", "Office", "36", "WPA2(PSK/AES/AES)"),
+        network("10:e9:92:aa:bb:cc", "", "14", ""),
+        network("DA:A1:19:00:00:01", "Phone", "165", "RSN(OWE)"),
+        network("00:11:22:00:00:01", "Cafe", "6", ""),
+    ];
+
+    let results = convert_to_wifi_data(&networks, &oui, &config);
+
+    assert_eq!(results.len(), 4);
+    let office = &results[0];
+    assert_eq!(office.mac, "00:1B:63:84:45:E6");
+    assert_eq!(office.manufacturer.as_deref(), Some("Apple, Inc."));
+    assert!(office.manufacturer_resolved);
+    assert_eq!(office.band, "5GHz");
+    assert_eq!(office.security, Security::Wpa2);
+    assert_eq!(office.signal_dbm, Some(-61));
+
+    let hidden = &results[1];
+    assert!(hidden.hidden);
+    assert_eq!(hidden.manufacturer.as_deref(), Some("Example Vendor"));
+    assert_eq!(hidden.band, "2.4GHz");
+    assert_eq!(hidden.security, Security::Open);
+
+    let phone = &results[2];
+    assert!(phone.randomized);
+    assert!(!phone.manufacturer_resolved);
+    assert_eq!(phone.band, "5GHz");
+    assert_eq!(phone.security, Security::Owe);
+
+    let unknown = &results[3];
+    assert!(!unknown.randomized);
+    assert!(!unknown.manufacturer_resolved);
+    assert_eq!(unknown.manufacturer.as_deref(), Some("Unknown"));
+}
+
+#[test]
+fn results_read_back_from_a_document_diff_against_a_new_scan() {
+    let oui = OuiDatabase::from_reader(REGISTRY.as_bytes()).unwrap();
+    let config: Config = serde_json::from_str("{}").unwrap();
+    let before = convert_to_wifi_data(&[
+        network("00:1B:63:84:45:E6", "Office", "36", "WPA2(PSK/AES/AES)"),
+        network("10:E9:92:AA:BB:CC", "Lab", "1", ""),
+    ], &oui, &config);
+    let summary = Summary::new(&before, Utc::now(), Utc::now());
+    let path = std::env::temp_dir().join(format!("wifi_module_previous_{}.json", std::process::id()));
+    std::fs::write(&path, serde_json::to_string(&results_document(&before, &summary, OutputShape::Map).unwrap()).unwrap()).unwrap();
+
+    let previous = read_results(path.to_str().unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let current = convert_to_wifi_data(&[
+        network("00:1B:63:84:45:E6", "Office", "149", "WPA2(PSK/AES/AES)"),
+        network("00:11:22:00:00:01", "Cafe", "6", ""),
+    ], &oui, &config);
+    let delta = ScanDelta::new(&previous, &current);
+
+    assert_eq!(previous, before);
+    assert_eq!(delta.added.len(), 1);
+    assert_eq!(delta.added[0].ssid, "Cafe");
+    assert_eq!(delta.removed.len(), 1);
+    assert_eq!(delta.removed[0].ssid, "Lab");
+    assert_eq!(delta.changed.len(), 1);
+    assert_eq!(delta.changed[0].fields, vec!["channel"]);
+}
+
+#[test]
+fn signal_floor_drops_weak_networks() {
+    let oui = OuiDatabase::from_reader(REGISTRY.as_bytes()).unwrap();
+    let mut near = network("00:1B:63:84:45:E6", "Near", "36", "");
+    near.signal_level = "-60".to_string();
+    let mut far = network("10:E9:92:AA:BB:CC", "Far", "1", "");
+    far.signal_level = "-88.00".to_string();
+    let mut unknown = network("00:11:22:00:00:01", "Unknown", "6", "");
+    unknown.signal_level = "n/a".to_string();
+    let networks = [near, far, unknown];
+
+    let kept = |json: &str| -> Vec<String> {
+        let config: Config = serde_json::from_str(json).unwrap();
+        convert_to_wifi_data(&networks, &oui, &config).into_iter().map(|item| item.ssid).collect()
+    };
+    assert_eq!(kept(r#"{ "min_signal_dbm": -75 }"#), ["Near", "Unknown"]);
+    assert_eq!(kept(r#"{ "min_signal_dbm": -75, "keep_unknown_signal": false }"#), ["Near"]);
+    assert_eq!(kept("{}").len(), 3);
+}
+
+#[test]
+fn schema_describes_every_record_field() {
+    let schema = serde_json::to_value(results_schema()).unwrap();
+    let described = schema["definitions"]["WifiData"]["properties"].as_object().unwrap();
+    let record = serde_json::to_value(wifi_module::WifiData::default()).unwrap();
+    for field in record.as_object().unwrap().keys() {
+        assert!(described.contains_key(field), "{} missing from the schema", field);
+    }
+    assert!(schema["properties"]["summary"].is_object());
+}
+
+#[test]
+fn gz_outputs_are_compressed_and_read_back() {
+    let oui = OuiDatabase::from_reader(REGISTRY.as_bytes()).unwrap();
+    let config: Config = serde_json::from_str("{}").unwrap();
+    let data = convert_to_wifi_data(&[
+        network("00:1B:63:84:45:E6", "Office", "36", "WPA2(PSK/AES/AES)"),
+        network("10:E9:92:AA:BB:CC", "Lab", "1", ""),
+    ], &oui, &config);
+    let dir = std::env::temp_dir();
+    let json_path = dir.join(format!("wifi_module_compressed_{}.json.gz", std::process::id()));
+    let ndjson_path = dir.join(format!("wifi_module_compressed_{}.ndjson.gz", std::process::id()));
+    let summary = Summary::new(&data, Utc::now(), Utc::now());
+    write_json_to_file(&serde_json::to_string(&results_document(&data, &summary, OutputShape::Map).unwrap()).unwrap(), json_path.to_str().unwrap()).unwrap();
+    // Appending adds a second gzip member to the same file
+    write_ndjson_to_file(&data, ndjson_path.to_str().unwrap(), true).unwrap();
+    write_ndjson_to_file(&data, ndjson_path.to_str().unwrap(), true).unwrap();
+
+    let raw = std::fs::read(&json_path).unwrap();
+    let read_back = read_results(json_path.to_str().unwrap()).unwrap();
+    let mut lines = String::new();
+    flate2::read::MultiGzDecoder::new(std::fs::File::open(&ndjson_path).unwrap()).read_to_string(&mut lines).unwrap();
+    std::fs::remove_file(&json_path).unwrap();
+    std::fs::remove_file(&ndjson_path).unwrap();
+
+    assert_eq!(&raw[..2], &[0x1f, 0x8b]);
+    assert_eq!(read_back, data);
+    assert_eq!(lines.lines().count(), 4);
+    assert_eq!(compressed_name("out.json", true), "out.json.gz");
+    assert_eq!(compressed_name("out.json.gz", true), "out.json.gz");
+    assert_eq!(compressed_name("-", true), "-");
+}
+
+#[test]
+fn empty_database_reports_every_manufacturer_as_unknown() {
+    let config: Config = serde_json::from_str("{}").unwrap();
+    let data = convert_to_wifi_data(&[network("00:1B:63:84:45:E6", "Office", "36", "WPA2(PSK/AES/AES)")], &OuiDatabase::default(), &config);
+
+    assert_eq!(data[0].manufacturer.as_deref(), Some("Unknown"));
+    assert!(!data[0].manufacturer_resolved);
+    assert_eq!(data[0].vendor, None);
+}
+
+#[test]
+fn from_scan_is_what_the_batch_conversion_produces() {
+    let oui = OuiDatabase::from_reader(REGISTRY.as_bytes()).unwrap();
+    let config: Config = serde_json::from_str(r#"{ "sanitize": "aggressive" }"#).unwrap();
+    let scanned = network("00:1b:63:84:45:e6", "Office\u{7}", "36", "WPA2(PSK/AES/AES)");
+
+    let record = WifiData::from_scan(&scanned, &oui, config.sanitize);
+
+    assert_eq!(record.mac, "00:1B:63:84:45:E6");
+    assert_eq!(record.ssid, "Office");
+    assert_eq!(record.vendor.as_deref(), Some("Apple"));
+    assert_eq!(convert_to_wifi_data(&[scanned], &oui, &config), vec![record]);
+}
+
+#[test]
+fn array_shape_lists_records_under_results() {
+    let oui = OuiDatabase::from_reader(REGISTRY.as_bytes()).unwrap();
+    let config: Config = serde_json::from_str(r#"{ "output_shape": "array" }"#).unwrap();
+    let data = convert_to_wifi_data(&[
+        network("00:1B:63:84:45:E6", "Office", "36", "WPA2(PSK/AES/AES)"),
+        network("10:E9:92:AA:BB:CC", "Lab", "1", ""),
+    ], &oui, &config);
+    let summary = Summary::new(&data, Utc::now(), Utc::now());
+
+    let document = results_document(&data, &summary, config.output_shape).unwrap();
+    let path = std::env::temp_dir().join(format!("wifi_module_array_{}.json", std::process::id()));
+    std::fs::write(&path, serde_json::to_string(&document).unwrap()).unwrap();
+    let read_back = read_results(path.to_str().unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(document["results"][1]["ssid"], "Lab");
+    assert!(!document.contains_key("1"));
+    assert!(document.contains_key("summary"));
+    assert_eq!(read_back, data);
+}
+
+#[test]
+fn exotic_ssid_bytes_keep_a_readable_name_and_their_hex() {
+    let oui = OuiDatabase::default();
+    let config: Config = serde_json::from_str("{}").unwrap();
+    let networks = [
+        network("02:00:00:00:00:01", r"\xe2\x98\x95 Cafe", "1", ""),
+        network("02:00:00:00:00:02", r"Lobby\x00\xff", "6", ""),
+        network("02:00:00:00:00:03", "📶 Guest", "11", ""),
+        network("02:00:00:00:00:04", r"back\slash\x4", "11", ""),
+    ];
+
+    let data = convert_to_wifi_data(&networks, &oui, &config);
+
+    assert_eq!((data[0].ssid.as_str(), data[0].ssid_hex.as_deref()), ("☕ Cafe", None));
+    assert_eq!((data[1].ssid.as_str(), data[1].ssid_hex.as_deref()), ("Lobby\u{FFFD}", Some("4c6f62627900ff")));
+    assert_eq!((data[2].ssid.as_str(), data[2].ssid_hex.as_deref()), ("📶 Guest", None));
+    assert_eq!((data[3].ssid.as_str(), data[3].ssid_hex.as_deref()), (r"back\slash\x4", None));
+}
@@ -0,0 +1,347 @@
+// Drives the scan loops with scripted results instead of the platform scanner
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tokio_stream::StreamExt;
+use tokio_wifiscanner::Wifi;
+use wifi_module::{hashed_mac, scan_once, scan_scheduled, scan_scheduled_watch, scan_stream, Config, OuiDatabase, PresenceTracker, ScanError, Scanner};
+
+// Returns one scripted batch per call, then empty scans once the script runs out
+struct MockScanner {
+    cycles: Mutex<VecDeque<Vec<Wifi>>>,
+}
+
+impl MockScanner {
+    fn new(cycles: Vec<Vec<Wifi>>) -> Self {
+        MockScanner { cycles: Mutex::new(cycles.into()) }
+    }
+}
+
+impl Scanner for MockScanner {
+    async fn scan(&self) -> Result<Vec<Wifi>, ScanError> {
+        Ok(self.cycles.lock().unwrap().pop_front().unwrap_or_default())
+    }
+}
+
+fn network(mac: &str, ssid: &str, channel: &str) -> Wifi {
+    Wifi {
+        mac: mac.to_string(),
+        ssid: ssid.to_string(),
+        channel: channel.to_string(),
+        signal_level: "-50".to_string(),
+        security: "WPA2(PSK/AES/AES)".to_string(),
+    }
+}
+
+fn config(json: &str) -> Config {
+    serde_json::from_str(json).expect("test config")
+}
+
+fn oui() -> OuiDatabase {
+    OuiDatabase::load_from_csv("src/database/oui.csv").expect("OUI database")
+}
+
+#[tokio::test(start_paused = true)]
+async fn scheduled_scan_splits_presence_when_a_device_leaves() {
+    let results = scheduled_away_and_back(r#""merge_gap_secs": 0"#).await;
+
+    assert_eq!(results.len(), 2);
+    let (away, steady) = (&results[0], &results[1]);
+    assert_eq!(away.mac, "10:E9:92:00:00:01");
+    // Seen alone at 0s and 15s, written as seconds elapsed when the scan finished at 20s
+    assert_eq!(away.wifi_durations, "20-20,5-5");
+    assert!(away.first_seen.unwrap() <= away.last_seen.unwrap());
+    assert_eq!(steady.wifi_durations, "5-20");
+    assert_eq!(away.total_dwell_secs, Some(0));
+    assert_eq!(steady.total_dwell_secs, Some(15));
+    // Four cycles ran: Away showed up in two of them, Steady in all
+    assert_eq!(away.sighting_count, Some(2));
+    assert_eq!(away.stability, Some(0.5));
+    assert_eq!(steady.sighting_count, Some(4));
+    assert_eq!(steady.stability, Some(1.0));
+    // Durations and timestamps read back exactly as they were written
+    let written = serde_json::to_string(away).unwrap();
+    assert_eq!(&serde_json::from_str::<wifi_module::WifiData>(&written).unwrap(), away);
+}
+
+#[tokio::test(start_paused = true)]
+async fn scheduled_scan_merges_windows_closer_than_the_gap() {
+    let results = scheduled_away_and_back(r#""merge_gap_secs": 20"#).await;
+
+    assert_eq!(results[0].wifi_durations, "5-20");
+    assert_eq!(results[0].total_dwell_secs, Some(15));
+}
+
+#[tokio::test(start_paused = true)]
+async fn scheduled_scan_reports_the_latest_ssid() {
+    let scanner = MockScanner::new(vec![
+        vec![network("10:E9:92:00:00:01", "Setup-1234", "1")],
+        vec![network("10:E9:92:00:00:01", "Kitchen", "6")],
+    ]);
+    let config = config(r#"{ "instant_scan": false, "scan_duration": 10, "poll_interval_secs": 5, "absence_threshold_secs": 6 }"#);
+
+    let results = scan_scheduled(&scanner, &config, &oui()).await.unwrap();
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].ssid, "Kitchen");
+    assert_eq!(results[0].channel, 6);
+    assert_eq!(results[0].channels_seen, vec![1, 6]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn scan_stream_yields_one_batch_per_interval() {
+    let scanner = MockScanner::new(vec![
+        vec![network("10:E9:92:00:00:01", "First", "1")],
+        vec![network("10:E9:92:00:00:01", "First", "1"), network("10:E9:92:00:00:02", "Second", "6")],
+    ]);
+    let stream = scan_stream(scanner, config("{}"), oui(), std::time::Duration::from_secs(10));
+    let batches: Vec<_> = stream.take(2).collect().await;
+
+    assert_eq!(batches.len(), 2);
+    assert_eq!(batches[0].as_ref().unwrap().len(), 1);
+    assert_eq!(batches[1].as_ref().unwrap()[1].ssid, "Second");
+}
+
+#[tokio::test(start_paused = true)]
+async fn presence_tracker_reports_a_departure_after_the_threshold() {
+    let away = network("10:E9:92:00:00:01", "Away", "1");
+    let steady = network("10:E9:92:00:00:02", "Steady", "6");
+    let scanner = MockScanner::new(vec![
+        vec![away.clone(), steady.clone()],
+        vec![away, steady.clone()],
+        vec![steady.clone()],
+        vec![steady],
+    ]);
+    let (config, oui) = (config("{}"), oui());
+    let mut presence = PresenceTracker::new();
+    let mut departures = Vec::new();
+    for _ in 0..4 {
+        let seen = scan_once(&scanner, &config, &oui).await.unwrap();
+        departures.push(presence.update(&seen, 15));
+        tokio::time::advance(std::time::Duration::from_secs(10)).await;
+    }
+
+    // Last seen at 10s, still within the threshold at 20s, gone at 30s
+    assert!(departures[2].is_empty());
+    assert_eq!(departures[3].len(), 1);
+    assert_eq!(departures[3][0].mac, "10:E9:92:00:00:01");
+    assert_eq!(departures[3][0].dwell_secs, 10);
+}
+
+#[tokio::test(start_paused = true)]
+async fn scheduled_scan_forgets_windows_older_than_the_retention() {
+    let results = scheduled_away_and_back(r#""history_retention_secs": 8"#).await;
+
+    // The 0s sighting of "Away" ended before the 7s cutoff of the last poll
+    assert_eq!(results[0].wifi_durations, "5-5");
+    assert_eq!(results[1].wifi_durations, "5-20");
+}
+
+// Reports every network as seen on "wlan1"
+struct TaggingScanner(MockScanner);
+
+impl Scanner for TaggingScanner {
+    async fn scan(&self) -> Result<Vec<Wifi>, ScanError> {
+        self.0.scan().await
+    }
+
+    fn interface_of(&self, _mac: &str) -> Option<String> {
+        Some("wlan1".to_string())
+    }
+}
+
+#[tokio::test]
+async fn instant_scan_tags_results_with_their_interface() {
+    let scanner = TaggingScanner(MockScanner::new(vec![vec![network("10:E9:92:00:00:01", "Office", "1")]]));
+    let results = scan_once(&scanner, &config("{}"), &oui()).await.unwrap();
+
+    assert_eq!(results[0].interface.as_deref(), Some("wlan1"));
+}
+
+// Takes a different, scripted amount of (paused) time for each scan, like a real backend
+struct SlowScanner {
+    latencies: Mutex<VecDeque<u64>>,
+    network: Wifi,
+}
+
+impl Scanner for SlowScanner {
+    async fn scan(&self) -> Result<Vec<Wifi>, ScanError> {
+        let latency = self.latencies.lock().unwrap().pop_front().unwrap_or(0);
+        tokio::time::sleep(std::time::Duration::from_millis(latency)).await;
+        Ok(vec![self.network.clone()])
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn scheduled_scan_keeps_one_window_despite_scan_jitter() {
+    // Sightings 5.1 to 8 seconds apart against a 5 second poll interval
+    let scanner = SlowScanner {
+        latencies: Mutex::new(VecDeque::from([100, 3000, 200, 1500, 2900, 0])),
+        network: network("10:E9:92:00:00:01", "Steady", "6"),
+    };
+    let config = config(r#"{ "instant_scan": false, "scan_duration": 40, "poll_interval_secs": 5 }"#);
+    let results = scan_scheduled(&scanner, &config, &oui()).await.unwrap();
+
+    assert_eq!(results.len(), 1);
+    assert!(!results[0].wifi_durations.contains(','), "split into {}", results[0].wifi_durations);
+}
+
+#[test]
+fn absence_threshold_must_exceed_the_poll_interval() {
+    let equal = config(r#"{ "poll_interval_secs": 5, "absence_threshold_secs": 5 }"#);
+    assert!(matches!(equal.validate(), Err(ScanError::ConfigInvalid(_))));
+}
+
+#[test]
+fn departures_need_a_threshold_of_at_least_the_monitor_interval() {
+    let defaults = config(r#"{ "report_departures": true }"#);
+    assert!(matches!(defaults.validate(), Err(ScanError::ConfigInvalid(_))));
+    let equal = config(r#"{ "report_departures": true, "monitor_interval_secs": 60, "absence_threshold_secs": 60 }"#);
+    assert!(equal.validate().is_ok());
+    let unreported = config(r#"{ "monitor_interval_secs": 60, "absence_threshold_secs": 15 }"#);
+    assert!(unreported.validate().is_ok());
+}
+
+#[tokio::test(start_paused = true)]
+async fn scheduled_scan_stops_once_the_watched_device_shows_up() {
+    let steady = network("10:E9:92:00:00:02", "Steady", "6");
+    let scanner = MockScanner::new(vec![
+        vec![steady.clone()],
+        vec![steady, network("10:E9:92:00:00:01", "Phone", "1")],
+    ]);
+    let config = config(r#"{ "instant_scan": false, "scan_duration": 60, "stop_on_mac": "10-e9-92-00-00-01" }"#);
+    let started = tokio::time::Instant::now();
+    let (results, spotted) = scan_scheduled_watch(&scanner, &config, &oui(), std::future::pending()).await.unwrap();
+
+    assert!(spotted);
+    assert_eq!(results.len(), 2);
+    // Found on the second poll, 5 seconds in, instead of running the full minute
+    assert_eq!(started.elapsed().as_secs(), 5);
+}
+
+#[tokio::test(start_paused = true)]
+async fn stopping_during_the_countdown_cancels_the_scan() {
+    let scanner = MockScanner::new(vec![vec![network("10:E9:92:00:00:01", "Phone", "1")]]);
+    let config = config(r#"{ "instant_scan": false, "start_after_duration": 120, "scan_duration": 120 }"#);
+    let started = tokio::time::Instant::now();
+    let stop = tokio::time::sleep(tokio::time::Duration::from_millis(2_500));
+    let (results, spotted) = scan_scheduled_watch(&scanner, &config, &oui(), stop).await.unwrap();
+
+    assert!(results.is_empty() && !spotted);
+    assert_eq!(scanner.cycles.lock().unwrap().len(), 1, "no scan should have run");
+    assert_eq!(started.elapsed().as_millis(), 2_500);
+}
+
+#[tokio::test(start_paused = true)]
+async fn stopping_mid_scan_keeps_what_was_gathered() {
+    let scanner = MockScanner::new(vec![vec![network("10:E9:92:00:00:01", "Phone", "1")]]);
+    let config = config(r#"{ "instant_scan": false, "start_after_duration": 2, "scan_duration": 120 }"#);
+    let stop = tokio::time::sleep(tokio::time::Duration::from_secs(10));
+    let (results, _) = scan_scheduled_watch(&scanner, &config, &oui(), stop).await.unwrap();
+
+    assert_eq!(results.len(), 1);
+}
+
+// Polls land at 0, 5, 10 and 15 seconds; "Away" is missing from the middle two
+async fn scheduled_away_and_back(extra_config: &str) -> Vec<wifi_module::WifiData> {
+    let away = network("10:E9:92:00:00:01", "Away", "1");
+    let steady = network("10:E9:92:00:00:02", "Steady", "6");
+    let scanner = MockScanner::new(vec![
+        vec![away.clone(), steady.clone()],
+        vec![steady.clone()],
+        vec![steady.clone()],
+        vec![away, steady],
+    ]);
+    let config = config(&format!(
+        r#"{{ "instant_scan": false, "scan_duration": 20, "poll_interval_secs": 5, "absence_threshold_secs": 6, {} }}"#,
+        extra_config
+    ));
+    scan_scheduled(&scanner, &config, &oui()).await.unwrap()
+}
+
+#[tokio::test]
+async fn repeated_instant_scans_keep_the_strongest_reading() {
+    let signal = |mac: &str, ssid: &str, dbm: &str| Wifi { signal_level: dbm.to_string(), ..network(mac, ssid, "6") };
+    let scanner = MockScanner::new(vec![
+        vec![signal("10:E9:92:00:00:01", "Office", "-70")],
+        vec![signal("10:e9:92:00:00:01", "Office", "-48"), signal("10:E9:92:00:00:02", "Lab", "-80")],
+        vec![signal("10:E9:92:00:00:01", "Office", "-60")],
+    ]);
+    let config = config(r#"{ "scan_count": 3 }"#);
+
+    let results = scan_once(&scanner, &config, &oui()).await.unwrap();
+
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].mac, "10:E9:92:00:00:01");
+    assert_eq!(results[0].signal_dbm, Some(-48));
+    assert_eq!(results[1].ssid, "Lab");
+}
+
+#[tokio::test]
+async fn manufacturer_filters_match_resolved_vendors() {
+    let batch = vec![
+        network("64:1B:2F:00:00:01", "Phone", "1"),
+        network("10:E9:92:00:00:02", "Printer", "6"),
+        network("12:34:56:00:00:03", "Random", "11"),
+    ];
+    let scan = |json: &'static str| {
+        let scanner = MockScanner::new(vec![batch.clone()]);
+        async move { scan_once(&scanner, &config(json), &oui()).await.unwrap() }
+    };
+
+    let included = scan(r#"{ "manufacturer_include": ["samsung"] }"#).await;
+    assert_eq!(included.iter().map(|item| item.ssid.as_str()).collect::<Vec<_>>(), vec!["Phone"]);
+    let excluded = scan(r#"{ "manufacturer_exclude": ["Ingram Micro Services", "randomized (locally administered)"] }"#).await;
+    assert_eq!(excluded.iter().map(|item| item.ssid.as_str()).collect::<Vec<_>>(), vec!["Phone"]);
+}
+
+#[tokio::test]
+async fn hashed_macs_are_stable_and_still_resolve_the_vendor() {
+    let batch = vec![network("64:1B:2F:00:00:01", "Phone", "1")];
+    let scanner = MockScanner::new(vec![batch.clone(), batch]);
+    let config = config(r#"{ "hash_mac": true, "mac_salt": "site-a" }"#);
+
+    let first = scan_once(&scanner, &config, &oui()).await.unwrap();
+    let second = scan_once(&scanner, &config, &oui()).await.unwrap();
+
+    assert_eq!(first[0].mac, hashed_mac("64:1b:2f:00:00:01", "site-a"));
+    assert_eq!(first[0].mac.len(), 16);
+    assert_eq!(first[0].mac, second[0].mac);
+    assert_ne!(first[0].mac, hashed_mac("64:1B:2F:00:00:01", "site-b"));
+    assert_eq!(first[0].vendor.as_deref(), Some("Samsung"));
+    assert!(serde_json::from_str::<Config>(r#"{ "hash_mac": true }"#).unwrap().validate().is_err());
+}
+
+#[tokio::test]
+async fn instant_scan_merges_channels_when_deduping_by_ssid() {
+    let scanner = MockScanner::new(vec![vec![
+        network("10:E9:92:00:00:01", "Office", "6"),
+        network("10:E9:92:00:00:02", "Office", "1"),
+        network("10:E9:92:00:00:03", "Guest", "11"),
+    ]]);
+    let config = config(r#"{ "dedupe_by": "ssid", "sort_by": "ssid" }"#);
+
+    let results = scan_once(&scanner, &config, &oui()).await.unwrap();
+
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].ssid, "Guest");
+    assert_eq!(results[1].ssid, "Office");
+    assert_eq!(results[1].channels, vec![1, 6]);
+}
+
+// Never answers, like an adapter whose backend has hung
+struct StalledScanner;
+
+impl Scanner for StalledScanner {
+    async fn scan(&self) -> Result<Vec<Wifi>, ScanError> {
+        std::future::pending().await
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn instant_scan_gives_up_on_a_stalled_backend() {
+    let config = config(r#"{ "scan_timeout_secs": 3, "scan_retries": 1 }"#);
+
+    let result = scan_once(&StalledScanner, &config, &oui()).await;
+
+    assert!(matches!(result, Err(ScanError::Timeout(limit)) if limit.as_secs() == 3));
+}